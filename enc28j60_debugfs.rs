@@ -0,0 +1,140 @@
+// SPDX-License-Identifier: GPL-2.0
+use {
+    crate::Enc28j60Adapter,
+    core::{
+        ffi::{c_char, c_int, c_void},
+        fmt,
+    },
+    kernel::{bindings, prelude::*, str::CStr, user_ptr::UserSlicePtr},
+};
+
+pub(crate) trait File {
+    const NAME: &'static CStr;
+    // Writable files get a `write` file operation and are created as 0600
+    const WRITABLE: bool = false;
+
+    fn show(adapter: &Enc28j60Adapter, m: &mut SeqFile) -> Result;
+
+    fn write(_adapter: &Enc28j60Adapter, _input: &str) -> Result {
+        Err(EINVAL)
+    }
+}
+
+pub(crate) struct SeqFile(*mut bindings::seq_file);
+
+impl fmt::Write for SeqFile {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // SAFETY: `self.0` is the seq_file being shown. On overflow seq_file
+        // retries `show` with a larger buffer by itself.
+        unsafe { bindings::seq_write(self.0, s.as_ptr() as _, s.len() as _) };
+        Ok(())
+    }
+}
+
+unsafe extern "C" fn open<T: File>(
+    inode: *mut bindings::inode,
+    file: *mut bindings::file,
+) -> c_int {
+    // SAFETY: `i_private` is the adapter passed to `debugfs_create_file`.
+    unsafe { bindings::single_open(file, Some(show::<T>), (*inode).i_private) }
+}
+
+unsafe extern "C" fn show<T: File>(m: *mut bindings::seq_file, _: *mut c_void) -> c_int {
+    // SAFETY: `private` is the adapter given to `single_open`, which outlives
+    // the files as `Dir` removes them before the adapter is dropped.
+    let adapter = unsafe { &*((*m).private as *const Enc28j60Adapter) };
+
+    match T::show(adapter, &mut SeqFile(m)) {
+        Ok(()) => 0,
+        Err(e) => e.to_kernel_errno(),
+    }
+}
+
+unsafe extern "C" fn write<T: File>(
+    file: *mut bindings::file,
+    buf: *const c_char,
+    count: usize,
+    _ppos: *mut bindings::loff_t,
+) -> isize {
+    let mut input = [0u8; 64];
+    let result = (|| -> Result<usize> {
+        let input = input.get_mut(..count).ok_or(EINVAL)?;
+        // SAFETY: `buf` is a user pointer valid for `count` bytes.
+        unsafe { UserSlicePtr::new(buf as _, count) }
+            .reader()
+            .read_slice(input)?;
+        let input = core::str::from_utf8(input).map_err(|_| EINVAL)?;
+
+        // SAFETY: `private_data` is the seq_file set up by `single_open`, whose
+        // `private` is the adapter as in `show`.
+        let adapter = unsafe {
+            &*((*((*file).private_data as *mut bindings::seq_file)).private
+                as *const Enc28j60Adapter)
+        };
+        T::write(adapter, input.trim())?;
+
+        Ok(count)
+    })();
+
+    match result {
+        Ok(count) => count as _,
+        Err(e) => e.to_kernel_errno() as _,
+    }
+}
+
+// A per-device directory at the debugfs root
+pub(crate) struct Dir {
+    dentry: *mut bindings::dentry,
+    fops: Vec<Box<bindings::file_operations>>,
+}
+
+impl Dir {
+    pub(crate) fn new(name: &CStr) -> Self {
+        // SAFETY: A null parent creates the directory at the debugfs root.
+        // debugfs errors are deliberately ignored as for any other driver.
+        let dentry =
+            unsafe { bindings::debugfs_create_dir(name.as_char_ptr(), core::ptr::null_mut()) };
+
+        Self {
+            dentry,
+            fops: Vec::new(),
+        }
+    }
+
+    // `adapter` must outlive `self`
+    pub(crate) fn create_file<T: File>(&mut self, adapter: &Enc28j60Adapter) -> Result {
+        let fops = Box::try_new(bindings::file_operations {
+            owner: crate::THIS_MODULE.as_ptr(),
+            open: Some(open::<T>),
+            read: Some(bindings::seq_read),
+            write: if T::WRITABLE { Some(write::<T>) } else { None },
+            llseek: Some(bindings::seq_lseek),
+            release: Some(bindings::single_release),
+            ..Default::default()
+        })?;
+        // `fops` must not be freed once the file exists
+        self.fops.try_reserve(1)?;
+
+        // SAFETY: `fops` is kept alive in `self.fops` until the file is removed in `drop`.
+        unsafe {
+            bindings::debugfs_create_file(
+                T::NAME.as_char_ptr(),
+                if T::WRITABLE { 0o600 } else { 0o400 },
+                self.dentry,
+                adapter as *const _ as *mut c_void,
+                &*fops,
+            )
+        };
+        self.fops.try_push(fops)?;
+
+        Ok(())
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        // SAFETY: `debugfs_remove` waits for in-flight callbacks, so `fops`
+        // and the adapter aren't used afterwards.
+        unsafe { bindings::debugfs_remove(self.dentry) };
+    }
+}
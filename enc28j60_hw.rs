@@ -1,8 +1,11 @@
 // SPDX-License-Identifier: GPL-2.0
 #![allow(dead_code)]
 
-use core::ops::{BitAnd, BitOr};
-use kernel::{prelude::*, spi};
+use core::{
+    ops::{BitAnd, BitOr, Not},
+    time::Duration,
+};
+use kernel::{c_str, prelude::*, spi, str::CStr};
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub(crate) enum Bank {
@@ -12,6 +15,126 @@ pub(crate) enum Bank {
     Bank3 = 3,
 }
 
+// The SPI transfers the chip needs. Register accesses go through this
+// rather than `spi::Device` so they can run against another backend.
+pub(crate) trait SpiBus {
+    fn write(&self, tx_buf: &[u8]) -> Result;
+    fn write_then_read(&self, tx_buf: &[u8], rx_buf: &mut [u8]) -> Result;
+}
+
+impl SpiBus for spi::Device {
+    fn write(&self, tx_buf: &[u8]) -> Result {
+        spi::Device::write(self, tx_buf)
+    }
+
+    fn write_then_read(&self, tx_buf: &[u8], rx_buf: &mut [u8]) -> Result {
+        spi::Device::write_then_read(self, tx_buf, rx_buf)
+    }
+}
+
+// Runs `transfer` again up to `retries` times while it fails on the bus.
+// Errors about the transfer itself, such as one too large for the
+// controller, would fail the same way again and are returned right away.
+pub(crate) fn retry_transfer(retries: u8, mut transfer: impl FnMut() -> Result) -> Result {
+    let mut tries = retries;
+    loop {
+        match transfer() {
+            Err(e) if tries > 0 && e != EINVAL && e != EMSGSIZE => {
+                tries -= 1;
+                kernel::delay::coarse_sleep(Duration::from_millis(1));
+            }
+            result => return result,
+        }
+    }
+}
+
+// `retry_transfer` for every transfer. Register accesses can be repeated
+// as they are, buffer memory ones can't as they move ERDPT/EWRPT.
+pub(crate) struct RetryBus<'a> {
+    pub(crate) bus: &'a dyn SpiBus,
+    pub(crate) retries: u8,
+}
+
+impl SpiBus for RetryBus<'_> {
+    fn write(&self, tx_buf: &[u8]) -> Result {
+        retry_transfer(self.retries, || self.bus.write(tx_buf))
+    }
+
+    fn write_then_read(&self, tx_buf: &[u8], rx_buf: &mut [u8]) -> Result {
+        retry_transfer(self.retries, || self.bus.write_then_read(tx_buf, rx_buf))
+    }
+}
+
+// Silicon revisions by their EREVID value, as listed in the errata
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub(crate) enum Revision {
+    B1 = 0x02,
+    B4 = 0x04,
+    B5 = 0x05,
+    B7 = 0x06,
+}
+
+impl Revision {
+    pub(crate) fn from_erevid(erevid: u8) -> Option<Self> {
+        match erevid {
+            0x02 => Some(Self::B1),
+            0x04 => Some(Self::B4),
+            0x05 => Some(Self::B5),
+            0x06 => Some(Self::B7),
+            _ => None,
+        }
+    }
+
+    // Workarounds needed by a revision, per the revisions the ENC28J60
+    // Silicon Errata (DS80349) lists as affected by each issue:
+    // - #12, transmit logic stalls after an abort: B1, B4, B5, B7
+    // - #14, ERXRDPT must be odd: B1, B4, B5, B7
+    // So far the sheet lists no fix for either. An unknown revision is newer
+    // than it and assumed to need everything.
+    pub(crate) fn errata(revision: Option<Self>) -> u8 {
+        match revision {
+            Some(Self::B1) => errata::ERXRDPT_ODD | errata::TX_LOGIC_RESET,
+            Some(Self::B4) => errata::ERXRDPT_ODD | errata::TX_LOGIC_RESET,
+            Some(Self::B5) => errata::ERXRDPT_ODD | errata::TX_LOGIC_RESET,
+            Some(Self::B7) => errata::ERXRDPT_ODD | errata::TX_LOGIC_RESET,
+            None => errata::ALL,
+        }
+    }
+
+    pub(crate) fn name(erevid: u8) -> &'static CStr {
+        match Self::from_erevid(erevid) {
+            Some(Self::B1) => c_str!("B1"),
+            Some(Self::B4) => c_str!("B4"),
+            Some(Self::B5) => c_str!("B5"),
+            Some(Self::B7) => c_str!("B7"),
+            None => c_str!("unknown"),
+        }
+    }
+}
+
+// Errata workarounds, see `Revision::errata`
+pub(crate) mod errata {
+    // ERXRDPT must only be written with odd values (#14)
+    pub(crate) const ERXRDPT_ODD: u8 = 0x01;
+    // The transmit logic may stall after an abort in half duplex, so it's
+    // reset before every transmission (#12)
+    pub(crate) const TX_LOGIC_RESET: u8 = 0x02;
+
+    pub(crate) const ALL: u8 = ERXRDPT_ODD | TX_LOGIC_RESET;
+}
+
+impl Bank {
+    pub(crate) fn from_index(index: u8) -> Option<Self> {
+        match index {
+            0 => Some(Self::Bank0),
+            1 => Some(Self::Bank1),
+            2 => Some(Self::Bank2),
+            3 => Some(Self::Bank3),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub(crate) struct ControlRegisterU8 {
     // None = common registers
@@ -35,6 +158,19 @@ impl ControlRegisterU8 {
             ..Self::new(bank, addr)
         }
     }
+
+    // Any register by its bank and address, for debugging
+    pub(crate) fn raw(bank: Bank, addr: u8) -> Option<Self> {
+        match (bank, addr) {
+            (_, 0x20..) => None,
+            (_, 0x1b..) => Some(Self::eth(None, addr)),
+            // MAC and MII registers
+            (Bank::Bank2, _) | (Bank::Bank3, 0x00..=0x05 | 0x0a) => {
+                Some(Self::new(Some(bank), addr))
+            }
+            _ => Some(Self::eth(Some(bank), addr)),
+        }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -56,6 +192,24 @@ impl ControlRegisterU16 {
 
         Self { low, high }
     }
+
+    // For pointers the hardware moves on its own, like ERXWRPT, where `read`
+    // could combine bytes from before and after a carry. Reading the high byte
+    // around the low byte detects that; a carry only happens every 256 bytes on
+    // the wire, so a retry is rarely needed.
+    pub(crate) fn read_stable(&self, bus: &dyn SpiBus) -> Result<u16> {
+        let mut high = self.high.read(bus, Command::Rcr)?;
+        for _ in 0..3 {
+            let low = self.low.read(bus, Command::Rcr)?;
+            let high_again = self.high.read(bus, Command::Rcr)?;
+            if high_again == high {
+                return Ok((high as u16) << 8 | low as u16);
+            }
+            high = high_again;
+        }
+
+        Err(EIO)
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -63,6 +217,9 @@ pub(crate) struct PhyRegister {
     pub(crate) addr: u8,
 }
 
+// SPI opcodes as the first byte on the wire: the 3-bit opcode of the datasheet
+// in bits 7:5, ORed with the 5-bit argument (register address) in bits 4:0,
+// e.g. WCR to ECON1 is 0x40 | 0x1f = 0x5f
 #[repr(u8)]
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub(crate) enum Command {
@@ -87,13 +244,17 @@ pub(crate) trait Register: Copy {
         + Clone
         + PartialEq
         + Eq
+        + Default
         + core::fmt::LowerHex
         + BitAnd<Output = Self::Size>
-        + BitOr<Output = Self::Size>;
+        + BitOr<Output = Self::Size>
+        + Not<Output = Self::Size>;
 
     fn bank(&self) -> Option<Bank>;
-    fn read(&self, _: &spi::Device, _: Command) -> Result<Self::Size>;
-    fn write(&self, _: &spi::Device, _: Command, data: Self::Size) -> Result;
+    // BFS/BFC only work on ETH registers, not on MAC/MII ones
+    fn is_eth(&self) -> bool;
+    fn read(&self, _: &dyn SpiBus, _: Command) -> Result<Self::Size>;
+    fn write(&self, _: &dyn SpiBus, _: Command, data: Self::Size) -> Result;
 }
 
 impl Register for ControlRegisterU8 {
@@ -103,20 +264,24 @@ impl Register for ControlRegisterU8 {
         self.bank
     }
 
-    fn read(&self, spidev: &spi::Device, command: Command) -> Result<Self::Size> {
+    fn is_eth(&self) -> bool {
+        self.eth
+    }
+
+    fn read(&self, bus: &dyn SpiBus, command: Command) -> Result<Self::Size> {
         let tx_buf = [(command as u8) | self.addr, 0];
         let mut rx_buf = [0u8; 2];
         let rx_len = if self.eth { 1 } else { 2 };
 
-        spidev.write_then_read(&tx_buf[..1], &mut rx_buf[..rx_len])?;
+        bus.write_then_read(&tx_buf[..1], &mut rx_buf[..rx_len])?;
 
         Ok(rx_buf[rx_len - 1])
     }
 
-    fn write(&self, spidev: &spi::Device, command: Command, data: Self::Size) -> Result {
+    fn write(&self, bus: &dyn SpiBus, command: Command, data: Self::Size) -> Result {
         let tx_buf = [(command as u8) | self.addr, data];
 
-        spidev.write(&tx_buf)
+        bus.write(&tx_buf)
     }
 }
 
@@ -128,16 +293,25 @@ impl Register for ControlRegisterU16 {
         self.low.bank
     }
 
-    fn read(&self, spidev: &spi::Device, command: Command) -> Result<Self::Size> {
-        let low = self.low.read(spidev, command)?;
-        let high = self.high.read(spidev, command)?;
+    fn is_eth(&self) -> bool {
+        self.low.eth
+    }
+
+    // The two bytes are separate transfers, so this may return a torn value for
+    // registers updated by the hardware. See `read_stable`.
+    // They can't be combined into one RCR: unlike RBM with ECON2.AUTOINC,
+    // which only applies to the buffer memory, RCR keeps shifting out the
+    // same register for as long as CS stays low.
+    fn read(&self, bus: &dyn SpiBus, command: Command) -> Result<Self::Size> {
+        let low = self.low.read(bus, command)?;
+        let high = self.high.read(bus, command)?;
 
         Ok((high as u16) << 8 | low as u16)
     }
 
-    fn write(&self, spidev: &spi::Device, command: Command, data: Self::Size) -> Result {
-        self.low.write(spidev, command, data as u8)?;
-        self.high.write(spidev, command, (data >> 8) as u8)
+    fn write(&self, bus: &dyn SpiBus, command: Command, data: Self::Size) -> Result {
+        self.low.write(bus, command, data as u8)?;
+        self.high.write(bus, command, (data >> 8) as u8)
     }
 }
 
@@ -232,9 +406,33 @@ pub(crate) mod register {
     pub(crate) const ERXWRPTH: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank0), 0x0f);
     pub(crate) const ERXWRPT: ControlRegisterU16 = ControlRegisterU16::new(ERXWRPTL, ERXWRPTH);
 
+    pub(crate) const EDMASTL: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank0), 0x10);
+    pub(crate) const EDMASTH: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank0), 0x11);
+    pub(crate) const EDMAST: ControlRegisterU16 = ControlRegisterU16::new(EDMASTL, EDMASTH);
+
+    pub(crate) const EDMANDL: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank0), 0x12);
+    pub(crate) const EDMANDH: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank0), 0x13);
+    pub(crate) const EDMAND: ControlRegisterU16 = ControlRegisterU16::new(EDMANDL, EDMANDH);
+
+    // EDMACSH holds the byte that goes first on the wire
+    pub(crate) const EDMACSL: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank0), 0x16);
+    pub(crate) const EDMACSH: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank0), 0x17);
+    pub(crate) const EDMACS: ControlRegisterU16 = ControlRegisterU16::new(EDMACSL, EDMACSH);
+
     //
     // Bank 1
     //
+    pub(crate) const EHT0: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank1), 0x00);
+    pub(crate) const EHT1: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank1), 0x01);
+    pub(crate) const EHT2: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank1), 0x02);
+    pub(crate) const EHT3: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank1), 0x03);
+    pub(crate) const EHT4: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank1), 0x04);
+    pub(crate) const EHT5: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank1), 0x05);
+    pub(crate) const EHT6: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank1), 0x06);
+    pub(crate) const EHT7: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank1), 0x07);
+    // The 64-bit hash table, EHT0 holding bits 7:0
+    pub(crate) const EHT: [ControlRegisterU8; 8] = [EHT0, EHT1, EHT2, EHT3, EHT4, EHT5, EHT6, EHT7];
+
     pub(crate) const ERXFCON: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank1), 0x18);
     pub(crate) mod erxfcon {
         // Unicast Filter Enable bit
@@ -335,6 +533,31 @@ pub(crate) mod register {
 
     pub(crate) const EREVID: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank3), 0x12);
 
+    // Not affected by resets, so CLKOUT keeps running across them
+    pub(crate) const ECOCON: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank3), 0x15);
+    pub(crate) mod ecocon {
+        // Divider of the 25 MHz main clock driven on CLKOUT:
+        // 000 = disabled, 001 = /1, 010 = /2, 011 = /3, 100 = /4 (power-on), 101 = /8
+        pub(crate) const COCON_MASK: u8 = 0x07;
+    }
+
+    pub(crate) const EFLOCON: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank3), 0x17);
+    pub(crate) mod eflocon {
+        // Read-only mirror of MACON3.FULDPX
+        pub(crate) const FULDPXS: u8 = 0x04;
+        // Flow Control Enable bits
+        // Full-Duplex: 11 = pause frame with a zero timer, then off
+        //              10 = periodic pause frames
+        //              01 = one pause frame, then off
+        // Half-Duplex: x1 = backpressure
+        pub(crate) const FCEN1: u8 = 0x02;
+        pub(crate) const FCEN0: u8 = 0x01;
+    }
+
+    pub(crate) const EPAUSL: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank3), 0x18);
+    pub(crate) const EPAUSH: ControlRegisterU8 = ControlRegisterU8::eth(Some(Bank::Bank3), 0x19);
+    pub(crate) const EPAUS: ControlRegisterU16 = ControlRegisterU16::new(EPAUSL, EPAUSH);
+
     //
     // PHY registers
     //
@@ -390,7 +613,56 @@ pub(crate) mod register {
         pub(crate) const PLNKIF: u16 = 1 << 4;
         pub(crate) const PGEIF: u16 = 1 << 1;
     }
+    // LED configuration, settable with the `microchip,phlcon` DT property:
+    //   15:14 reserved, write 0     13:12 reserved, write 1
+    //   11:8 LACFG, 7:4 LBCFG (LEDA/LEDB): 0001 = TX, 0010 = RX,
+    //     0011 = collisions, 0100 = link, 0101 = duplex, 0111 = TX/RX,
+    //     1000 = on, 1001 = off, 1010 = fast blink, 1011 = slow blink,
+    //     1100 = link and RX, 1101 = link and TX/RX, 1110 = duplex and collisions
+    //   3:2 LFRQ stretch length: 00 = 40ms, 01 = 73ms, 10 = 139ms
+    //   1 STRCH: stretch LED events, 0 reserved, write 0
     pub(crate) const PHLCON: PhyRegister = PhyRegister { addr: 0x14 };
+    pub(crate) mod phlcon {
+        // Bits that must be written as 0 and as 1
+        pub(crate) const RESERVED_0: u16 = 0xc001;
+        pub(crate) const RESERVED_1: u16 = 0x3000;
+    }
+
+    //
+    // Registers that boards may override from the device tree
+    //
+    pub(crate) const OVERRIDABLE_CONTROL: [ControlRegisterU8; 9] = [
+        ERXFCON, MACON1, MACON3, MACON4, MABBIPG, MAIPGL, MAIPGH, MAMXFLL, MAMXFLH,
+    ];
+    pub(crate) const OVERRIDABLE_PHY: [PhyRegister; 3] = [PHCON1, PHCON2, PHLCON];
+}
+
+// A register write requested by the `microchip,register-overrides` DT property
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum RegOverride {
+    Control(ControlRegisterU8, u8),
+    Phy(PhyRegister, u16),
+}
+
+impl RegOverride {
+    const PHY_FLAG: u32 = 0x100;
+
+    // `reg` is `(bank << 5) | addr` for control registers and `0x100 | addr`
+    // for PHY registers. Returns None for registers not allowed to be overridden
+    // or values that don't fit in the register.
+    pub(crate) fn new(reg: u32, value: u32) -> Option<Self> {
+        if reg & Self::PHY_FLAG != 0 {
+            let phy = register::OVERRIDABLE_PHY
+                .iter()
+                .find(|r| Self::PHY_FLAG | r.addr as u32 == reg)?;
+            Some(Self::Phy(*phy, u16::try_from(value).ok()?))
+        } else {
+            let ctrl = register::OVERRIDABLE_CONTROL.iter().find(
+                |r| matches!(r.bank, Some(bank) if (bank as u32) << 5 | r.addr as u32 == reg),
+            )?;
+            Some(Self::Control(*ctrl, u8::try_from(value).ok()?))
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -408,15 +680,54 @@ impl TxStatusVector {
 
     pub(crate) fn new(data: &[u8; Self::size()]) -> TxStatusVector {
         TxStatusVector {
-            byte_count: ((data[1] as u16) << 8) | data[0] as u16,
-            status1: ((data[3] as u16) << 8) | data[2] as u16,
-            total_bytes_transmitted: ((data[5] as u16) << 8) | data[4] as u16,
+            byte_count: u16::from_le_bytes([data[0], data[1]]),
+            status1: u16::from_le_bytes([data[2], data[3]]),
+            total_bytes_transmitted: u16::from_le_bytes([data[4], data[5]]),
             status2: data[6],
         }
     }
+
+    pub(crate) fn status(&self, mask: TsvStatus) -> bool {
+        self.status1 & mask as u16 != 0
+    }
+
+    // Collisions while sending this frame. Wraps at 16, so an excessive
+    // collision abort reads as 0 here.
+    pub(crate) fn collision_count(&self) -> u8 {
+        (self.status1 & 0x0f) as u8
+    }
+
+    pub(crate) fn late_collision(&self) -> bool {
+        self.status(TsvStatus::LateCollision)
+    }
+
+    // The MAC gave up on the frame rather than sending it in full
+    pub(crate) fn aborted(&self) -> bool {
+        self.status(TsvStatus::ExcessiveCollision)
+            || self.status(TsvStatus::LateCollision)
+            || self.status(TsvStatus::ExcessiveDefer)
+            || self.status(TsvStatus::Giant)
+            || self.status(TsvStatus::Underrun)
+    }
+}
+
+// Bits 31:16 of the TSV, i.e. `TxStatusVector::status1`
+#[repr(u16)]
+pub(crate) enum TsvStatus {
+    CrcError = 1 << 4,
+    LengthCheckError = 1 << 5,
+    LengthOutOfRange = 1 << 6,
+    Done = 1 << 7,
+    Multicast = 1 << 8,
+    Broadcast = 1 << 9,
+    PacketDefer = 1 << 10,
+    ExcessiveDefer = 1 << 11,
+    ExcessiveCollision = 1 << 12,
+    LateCollision = 1 << 13,
+    Giant = 1 << 14,
+    Underrun = 1 << 15,
 }
 
-#[repr(packed)]
 #[derive(Copy, Clone, Debug)]
 pub(crate) struct RxStatusVector {
     pub(crate) next_ptr: u16,
@@ -426,14 +737,14 @@ pub(crate) struct RxStatusVector {
 
 impl RxStatusVector {
     pub(crate) const fn size() -> usize {
-        core::mem::size_of::<Self>()
+        6
     }
 
     pub(crate) fn new(data: &[u8; Self::size()]) -> Self {
         Self {
-            next_ptr: ((data[1] as u16) << 8) | data[0] as u16,
-            byte_count: ((data[3] as u16) << 8) | data[2] as u16,
-            status: ((data[5] as u16) << 8) | data[4] as u16,
+            next_ptr: u16::from_le_bytes([data[0], data[1]]),
+            byte_count: u16::from_le_bytes([data[2], data[3]]),
+            status: u16::from_le_bytes([data[4], data[5]]),
         }
     }
 
@@ -1,18 +1,29 @@
 // SPDX-License-Identifier: GPL-2.0
 use {
-    core::time::Duration,
+    core::{
+        ffi::{c_ulong, c_void},
+        fmt::Write,
+        ptr,
+        sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering},
+        time::Duration,
+    },
     kernel::{
-        bindings,
+        bindings, c_str,
         device::RawDevice,
         driver, irq, module_spi_driver, net, of,
         prelude::*,
         spi,
-        sync::{smutex::Mutex, Arc, SpinLock, UniqueArc},
-        types::ForeignOwnable,
+        str::{CStr, CString},
+        sync::{
+            smutex::{Guard, Mutex},
+            Arc, SpinLock, UniqueArc,
+        },
+        types::{ForeignOwnable, Opaque},
         workqueue,
     },
 };
 
+mod enc28j60_debugfs;
 mod enc28j60_hw;
 use enc28j60_hw::register::*;
 use enc28j60_hw::*;
@@ -20,20 +31,589 @@ use enc28j60_hw::*;
 type FifoRange = core::ops::RangeInclusive<u16>;
 const RXFIFO_INIT: FifoRange = 0x0000..=0x19ff;
 const TXFIFO_INIT: FifoRange = 0x1a00..=0x1fff;
+const BUFFER_SIZE: u16 = 0x2000;
 
 const ENC28J60_LAMPS_MODE: u16 = 0x3476;
+// The fastest SPI clock of the datasheet
+const SPI_MAX_SPEED_HZ: u32 = 20_000_000;
 const ETH_MAX_FRAME_LEN: u16 = 1518;
+const VLAN_MAX_FRAME_LEN: u16 = ETH_MAX_FRAME_LEN + bindings::VLAN_HLEN as u16;
+// Shorter frames are runts, which are dropped as length errors
+const ETH_MIN_FRAME_LEN: u16 = 64;
+// Covers minimum-size frames such as TCP ACKs
+const RX_SINGLE_READ_DEFAULT: u16 = 64;
+// Enough to cover a burst of frames the size of the RX FIFO head read
+const RX_POOL_SIZE: usize = 8;
+// Frames received per IRQ work run before the driver lock is released. NAPI
+// isn't an option as its poll can't sleep, which every SPI transfer does.
+const RX_BUDGET: u8 = 16;
+// How long ESTAT.CLKRDY is polled before the chip is considered dead
+const CLKRDY_TIMEOUT: Duration = Duration::from_millis(10);
+// An MII operation takes 10.24us, so this only trips on a hung chip
+const MII_TIMEOUT: Duration = Duration::from_millis(10);
+// A maximum-sized frame takes 1.2ms at 10 Mbps to be received
+const RX_DRAIN_TIMEOUT: Duration = Duration::from_millis(10);
+// A maximum-sized frame takes 1.2ms at 10 Mbps, plus half-duplex backoff
+const TX_DRAIN_TIMEOUT: Duration = Duration::from_millis(10);
+// The DMA checksums a maximum-sized frame in well under a millisecond
+const DMA_TIMEOUT: Duration = Duration::from_millis(10);
+// At most one RX error message per interval, a bad cable fails every frame
+const RX_ERR_LOG_INTERVAL: Duration = Duration::from_secs(1);
+// offsetof(struct udphdr, check), the only checksum where 0 means none
+const UDP_CSUM_OFFSET: u16 = 6;
+// Upper bound of the `tx_queue_len` module parameter, see `TxQueue`
+const TX_QUEUE_MAX: usize = 8;
+// Link and RX latency of boards without an IRQ, unless set by the DT
+const POLL_INTERVAL_DEFAULT_US: u32 = 10_000;
+// Further attempts at a frame that hit a late collision
+const TX_MAX_RETRIES: u8 = 3;
+// A frame takes ~1.2ms on the wire, so this only trips on a lost completion
+const TX_TIMEOUT_MS: u32 = 4000;
+
+// `ethtool --set-priv-flags` flags by their bit
+const ETHTOOL_PRIV_FLAGS: [&str; 1] = ["rx-burst-reserve"];
+const PRIV_FLAG_RX_RESERVE: u32 = 1 << 0;
+
+// Reserve the `rx-burst-reserve` flag turns on without one from the device
+// tree: room for one more frame of the largest size
+const RX_RESERVE_DEFAULT: u16 = VLAN_MAX_FRAME_LEN + RxStatusVector::size() as u16;
+
+// Counters reported by `ethtool -S`: the error breakdown of
+// `net_device_stats` and driver counters it has no room for. Names and values
+// come from this one table so that they can't get out of step.
+const ETHTOOL_STATS: &[(&str, fn(&Enc28j60Driver) -> u64)] = &[
+    ("rx_broadcast", |driver| driver.rx_broadcast),
+    ("rx_pause_frames", |driver| driver.rx_pause_frames),
+    ("rx_own_source_mac", |driver| driver.rx_own_src),
+    ("rx_crc_errors", |driver| {
+        driver.netdev_stat(|s| s.rx_crc_errors)
+    }),
+    ("rx_length_errors", |driver| {
+        driver.netdev_stat(|s| s.rx_length_errors)
+    }),
+    ("rx_overruns", |driver| {
+        driver.netdev_stat(|s| s.rx_over_errors)
+    }),
+    ("tx_collisions", |driver| {
+        driver.netdev_stat(|s| s.collisions)
+    }),
+    ("tx_aborts", |driver| {
+        driver.netdev_stat(|s| s.tx_aborted_errors)
+    }),
+    ("tx_late_collisions", |driver| {
+        driver.netdev_stat(|s| s.tx_window_errors)
+    }),
+    ("bank_switches", |driver| driver.bank_switches),
+    ("phy_jabber", |driver| driver.phy_jabbers),
+];
+
+// `ethtool -t` tests in the order of their results
+const ETHTOOL_TESTS: [&str; 2] = ["Register test  (online)", "Loopback test  (offline)"];
+
+// Layout of the `ethtool -d` dump, to be bumped on any change: the banked
+// registers 0x00-0x1a of banks 0-3, the common registers 0x1b-0x1f, then
+// `REGS_DUMP_PHY` as little-endian u16. PHIR is left out as reading it
+// acknowledges PHY interrupts.
+const REGS_DUMP_VERSION: u32 = 1;
+const REGS_DUMP_PHY: [PhyRegister; 8] =
+    [PHCON1, PHSTAT1, PHID1, PHID2, PHCON2, PHSTAT2, PHIE, PHLCON];
+const REGS_DUMP_LEN: usize = 4 * 0x1b + 5 + REGS_DUMP_PHY.len() * 2;
 
 #[allow(non_upper_case_globals)]
 const from_dev: fn(&dyn RawDevice) -> kernel::device::Device = kernel::device::Device::from_dev;
 
+// `dev_*!` for messages that `ethtool -s msglvl` can silence, by the
+// `NETIF_MSG_*_BIT` they belong to
+macro_rules! netif_msg {
+    ($driver:expr, $bit:ident, $dev_macro:ident, $($arg:tt)*) => {
+        if $driver.msg_enable & (1 << bindings::$bit) != 0 {
+            $dev_macro!(from_dev(&$driver.spidev), $($arg)*);
+        }
+    };
+}
+
+const MSG_ENABLE_DEFAULT: u32 = 1 << bindings::NETIF_MSG_LINK_BIT
+    | 1 << bindings::NETIF_MSG_IFUP_BIT
+    | 1 << bindings::NETIF_MSG_IFDOWN_BIT;
+
+// `net::Device` and `net::SkBuff` are transparent wrappers of the C structs
+fn raw_netdev(dev: &net::Device) -> *mut bindings::net_device {
+    dev as *const net::Device as *mut bindings::net_device
+}
+
+fn raw_skb(skb: &net::SkBuff) -> *mut bindings::sk_buff {
+    skb as *const net::SkBuff as *mut bindings::sk_buff
+}
+
+// `csum_start` relative to the frame data and `csum_offset` of an skb whose
+// checksum is left to us (CHECKSUM_PARTIAL)
+fn skb_csum_partial(skb: &net::SkBuff) -> Option<(u16, u16)> {
+    let skb = raw_skb(skb);
+    // SAFETY: `SkBuff` wraps a valid `sk_buff`, and `csum_start`/`csum_offset`
+    // are valid with CHECKSUM_PARTIAL.
+    unsafe {
+        if (*skb).ip_summed() as u32 != bindings::CHECKSUM_PARTIAL {
+            return None;
+        }
+        let headroom = (*skb).data.offset_from((*skb).head) as u16;
+        let csum = &(*skb).__bindgen_anon_4.__bindgen_anon_1;
+        Some((csum.csum_start - headroom, csum.csum_offset))
+    }
+}
+
+// Software RX timestamp, taken when the frame has been read from the chip
+fn skb_rx_timestamp(skb: &net::SkBuff) {
+    let skb = raw_skb(skb);
+    // SAFETY: `SkBuff` wraps a valid `sk_buff` which isn't queued anywhere yet.
+    unsafe { (*skb).__bindgen_anon_3.tstamp = bindings::ktime_get_real() };
+}
+
+fn raw_spi(spidev: &spi::Device) -> *mut bindings::spi_device {
+    kernel::container_of!(spidev.raw_device(), bindings::spi_device, dev) as *mut _
+}
+
+// Copies `src` into a fixed-size C string field, truncating to keep the NUL
+fn copy_to_cstr(dst: &mut [core::ffi::c_char], src: &[u8]) {
+    let len = src.len().min(dst.len().saturating_sub(1));
+    for (d, s) in dst.iter_mut().zip(&src[..len]) {
+        *d = *s as _;
+    }
+    if let Some(nul) = dst.get_mut(len) {
+        *nul = 0;
+    }
+}
+
+// `is_valid_ether_addr`: neither multicast nor all zeros
+fn is_valid_ether_addr(addr: &[u8]) -> bool {
+    addr[0] & 0x01 == 0 && addr.iter().any(|b| *b != 0)
+}
+
+fn of_node(spidev: &spi::Device) -> *mut bindings::device_node {
+    // SAFETY: `raw_device` is valid as long as `spidev` is alive.
+    unsafe { (*spidev.raw_device()).of_node }
+}
+
+fn of_property_present(spidev: &spi::Device, name: &CStr) -> bool {
+    // SAFETY: `of_find_property` accepts a null node and `name` is NUL-terminated.
+    !unsafe {
+        bindings::of_find_property(of_node(spidev), name.as_char_ptr(), core::ptr::null_mut())
+    }
+    .is_null()
+}
+
+// Returns None if the property is absent
+fn of_read_u32_array<'a>(
+    spidev: &spi::Device,
+    name: &CStr,
+    buf: &'a mut [u32],
+) -> Result<Option<&'a [u32]>> {
+    if !of_property_present(spidev, name) {
+        return Ok(None);
+    }
+
+    // SAFETY: The node has the property and `buf` is valid for `buf.len()` elements.
+    let ret = unsafe {
+        bindings::of_property_read_variable_u32_array(
+            of_node(spidev),
+            name.as_char_ptr(),
+            buf.as_mut_ptr(),
+            1,
+            buf.len(),
+        )
+    };
+    if ret < 0 {
+        return Err(Error::from_kernel_errno(ret));
+    }
+
+    Ok(Some(&buf[..ret as usize]))
+}
+
+// The optional `vdd-supply` of the chip, disabled and released on drop
+struct Regulator {
+    ptr: *mut bindings::regulator,
+    enabled: bool,
+}
+
+impl Regulator {
+    // Returns None if the board has no supply for the chip
+    fn get_optional(spidev: &spi::Device, name: &CStr) -> Result<Option<Self>> {
+        // SAFETY: `raw_device` is valid as long as `spidev` is alive.
+        let ptr =
+            unsafe { bindings::regulator_get_optional(spidev.raw_device(), name.as_char_ptr()) };
+        let errno = ptr as isize;
+        if (-(bindings::MAX_ERRNO as isize)..0).contains(&errno) {
+            // EPROBE_DEFER until the regulator driver has probed
+            return match errno as i32 {
+                e if e == -(bindings::ENODEV as i32) => Ok(None),
+                e => Err(Error::from_kernel_errno(e)),
+            };
+        }
+
+        Ok(Some(Self {
+            ptr,
+            enabled: false,
+        }))
+    }
+
+    fn enable(&mut self) -> Result {
+        if !self.enabled {
+            // SAFETY: `ptr` is a regulator we got in `get_optional`.
+            let ret = unsafe { bindings::regulator_enable(self.ptr) };
+            if ret < 0 {
+                return Err(Error::from_kernel_errno(ret));
+            }
+            self.enabled = true;
+        }
+        Ok(())
+    }
+
+    fn disable(&mut self) -> Result {
+        if self.enabled {
+            // SAFETY: `ptr` is a regulator we enabled in `enable`.
+            let ret = unsafe { bindings::regulator_disable(self.ptr) };
+            if ret < 0 {
+                return Err(Error::from_kernel_errno(ret));
+            }
+            self.enabled = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Regulator {
+    fn drop(&mut self) {
+        let _ = self.disable();
+        // SAFETY: `ptr` is a regulator we got in `get_optional`.
+        unsafe { bindings::regulator_put(self.ptr) };
+    }
+}
+
+// Board-specific settings read from the device tree at probe time
+struct BoardConfig {
+    // Applied in order after the built-in defaults of `init_hardware`
+    reg_overrides: Vec<RegOverride>,
+    // Derive the MAC address from the board serial number instead of a random one
+    stable_macaddr: bool,
+    // Initial `Enc28j60Driver::rx_reserve`, 0 = disabled
+    rx_reserve: u16,
+    // Repeats of an SPI transfer that failed on the bus, see `retry_transfer`
+    spi_retries: u8,
+    // Initial duplex of the MAC and PHY, full unless the board asks for half
+    full_duplex: bool,
+    // `spi-max-frequency` clamped to what the chip supports
+    spi_speed_hz: u32,
+    // ECOCON divider for CLKOUT, None to leave the power-on 6.25 MHz
+    clkout: Option<u8>,
+    // Polling interval for boards without the INT pin wired, 0 = IRQ
+    poll_interval_us: u32,
+    // LED modes of how the board wires LEDA/LEDB
+    phlcon: u16,
+}
+
+impl BoardConfig {
+    const MAX_REG_OVERRIDES: usize = 32;
+
+    fn from_dt(spidev: &spi::Device) -> Result<Self> {
+        Ok(Self {
+            reg_overrides: Self::read_reg_overrides(spidev)?,
+            stable_macaddr: of_property_present(spidev, c_str!("microchip,stable-mac-address")),
+            rx_reserve: Self::read_rx_reserve(spidev)?,
+            spi_retries: Self::read_spi_retries(spidev)?,
+            full_duplex: Self::read_full_duplex(spidev)?,
+            spi_speed_hz: Self::clamp_spi_speed(spidev)?,
+            clkout: Self::read_clkout(spidev)?,
+            poll_interval_us: Self::read_poll_interval(spidev)?,
+            phlcon: Self::read_phlcon(spidev)?,
+        })
+    }
+
+    fn read_phlcon(spidev: &spi::Device) -> Result<u16> {
+        let mut phlcon = [0u32];
+        let phlcon = match of_read_u32_array(spidev, c_str!("microchip,phlcon"), &mut phlcon)? {
+            Some(phlcon) => phlcon[0],
+            None => return Ok(ENC28J60_LAMPS_MODE),
+        };
+
+        match u16::try_from(phlcon) {
+            Ok(value)
+                if value & phlcon::RESERVED_0 == 0
+                    && value & phlcon::RESERVED_1 == phlcon::RESERVED_1 =>
+            {
+                Ok(value)
+            }
+            _ => {
+                dev_err!(from_dev(spidev), "invalid PHLCON value {:#x}\n", phlcon);
+                Err(EINVAL)
+            }
+        }
+    }
+
+    fn read_poll_interval(spidev: &spi::Device) -> Result<u32> {
+        if spidev.get_irq() > 0 {
+            return Ok(0);
+        }
+
+        let mut interval = [POLL_INTERVAL_DEFAULT_US];
+        let interval =
+            match of_read_u32_array(spidev, c_str!("microchip,poll-interval-us"), &mut interval)? {
+                Some(interval) => interval[0],
+                None => POLL_INTERVAL_DEFAULT_US,
+            };
+        if interval == 0 {
+            dev_err!(from_dev(spidev), "no IRQ and a zero poll interval\n");
+            return Err(EINVAL);
+        }
+
+        dev_info!(from_dev(spidev), "no IRQ, polling every {} us\n", interval);
+        Ok(interval)
+    }
+
+    fn read_clkout(spidev: &spi::Device) -> Result<Option<u8>> {
+        let mut freq = [0u32];
+        let freq = match of_read_u32_array(spidev, c_str!("microchip,clkout-frequency"), &mut freq)?
+        {
+            Some(freq) => freq[0],
+            None => return Ok(None),
+        };
+
+        // Frequencies of the ECOCON dividers, 0 disables CLKOUT
+        let cocon = match freq {
+            0 => 0b000,
+            25_000_000 => 0b001,
+            12_500_000 => 0b010,
+            8_333_333 => 0b011,
+            6_250_000 => 0b100,
+            3_125_000 => 0b101,
+            _ => {
+                dev_err!(
+                    from_dev(spidev),
+                    "unsupported CLKOUT frequency {} Hz\n",
+                    freq
+                );
+                return Err(EINVAL);
+            }
+        };
+
+        Ok(Some(cocon))
+    }
+
+    // Register reads get corrupted above the chip's limit, so a faster
+    // `spi-max-frequency` is lowered rather than trusted
+    fn clamp_spi_speed(spidev: &spi::Device) -> Result<u32> {
+        let spi = raw_spi(spidev);
+        // SAFETY: `spi` is the bound SPI device, not in use by the driver yet.
+        let requested = unsafe { (*spi).max_speed_hz };
+        if requested != 0 && requested <= SPI_MAX_SPEED_HZ {
+            return Ok(requested);
+        }
+
+        if requested > SPI_MAX_SPEED_HZ {
+            dev_warn!(
+                from_dev(spidev),
+                "spi-max-frequency {} Hz exceeds the chip limit, using {} Hz\n",
+                requested,
+                SPI_MAX_SPEED_HZ
+            );
+        }
+        // SAFETY: As above. `spi_setup` applies the new speed to the controller.
+        let ret = unsafe {
+            (*spi).max_speed_hz = SPI_MAX_SPEED_HZ;
+            bindings::spi_setup(spi)
+        };
+        if ret < 0 {
+            return Err(Error::from_kernel_errno(ret));
+        }
+
+        // The controller may not reach the limit either
+        // SAFETY: As above.
+        Ok(unsafe { (*spi).max_speed_hz })
+    }
+
+    fn read_full_duplex(spidev: &spi::Device) -> Result<bool> {
+        let present =
+            |names: [&CStr; 2]| names.iter().any(|&name| of_property_present(spidev, name));
+        let full = present([c_str!("microchip,full-duplex"), c_str!("full-duplex")]);
+        let half = present([c_str!("microchip,half-duplex"), c_str!("half-duplex")]);
+
+        if full && half {
+            dev_err!(from_dev(spidev), "Both full and half duplex requested\n");
+            return Err(EINVAL);
+        }
+
+        Ok(!half)
+    }
+
+    fn read_spi_retries(spidev: &spi::Device) -> Result<u8> {
+        let mut retries = [0u32];
+        match of_read_u32_array(spidev, c_str!("microchip,spi-retries"), &mut retries)? {
+            Some(&[retries]) => u8::try_from(retries).map_err(|_| EINVAL),
+            _ => Ok(0),
+        }
+    }
+
+    fn read_rx_reserve(spidev: &spi::Device) -> Result<u16> {
+        let mut reserve = [0u32];
+        let reserve =
+            match of_read_u32_array(spidev, c_str!("microchip,rx-burst-reserve"), &mut reserve)? {
+                Some(reserve) => reserve[0],
+                None => return Ok(0),
+            };
+
+        let rx_size = (RXFIFO_INIT.end() - RXFIFO_INIT.start() + 1) as u32;
+        if reserve >= rx_size {
+            dev_err!(
+                from_dev(spidev),
+                "RX burst reserve {} exceeds the RX FIFO size {}\n",
+                reserve,
+                rx_size
+            );
+            return Err(EINVAL);
+        }
+
+        Ok(reserve as u16)
+    }
+
+    // microchip,register-overrides = <reg value>, ...;
+    // See `RegOverride::new` for the encoding of `reg`.
+    fn read_reg_overrides(spidev: &spi::Device) -> Result<Vec<RegOverride>> {
+        let mut cells = [0u32; 2 * Self::MAX_REG_OVERRIDES];
+        let cells =
+            match of_read_u32_array(spidev, c_str!("microchip,register-overrides"), &mut cells)? {
+                Some(cells) => cells,
+                None => return Ok(Vec::new()),
+            };
+
+        if cells.len() % 2 != 0 {
+            dev_err!(
+                from_dev(spidev),
+                "register overrides must be <reg value> pairs\n"
+            );
+            return Err(EINVAL);
+        }
+
+        let mut overrides = Vec::try_with_capacity(cells.len() / 2)?;
+        for pair in cells.chunks_exact(2) {
+            let reg_override = RegOverride::new(pair[0], pair[1]).ok_or_else(|| {
+                dev_err!(
+                    from_dev(spidev),
+                    "register override {:#x}={:#x} is not allowed\n",
+                    pair[0],
+                    pair[1]
+                );
+                EINVAL
+            })?;
+            overrides.try_push(reg_override)?;
+        }
+
+        Ok(overrides)
+    }
+}
+
 struct Enc28j60Driver {
     bank: Bank,
     spidev: spi::Device,
+    config: BoardConfig,
     netdev_reg: Option<net::Registration<Enc28j60Adapter>>,
     irq: Option<irq::ThreadedRegistration<Enc28j60Adapter>>,
+    debugfs: Option<enc28j60_debugfs::Dir>,
+    // Register selected through the debugfs `reg` file
+    debug_reg: Option<ControlRegisterU8>,
     next_packet_ptr: u16,
-    xfer_buf: [u8; 4 + ETH_MAX_FRAME_LEN as usize],
+    // Frames up to this size are read together with their RSV in one SPI
+    // message. The rest of larger frames is read in chunks of `rx_chunk_max`,
+    // the most the SPI controller takes in one transfer after the opcode.
+    rx_single_read_max: u16,
+    rx_chunk_max: usize,
+    // Whether flow control is holding off the link partner, see `update_flow_control`
+    rx_paused: bool,
+    // Free RX FIFO bytes below which the link partner is paused, 0 = disabled.
+    // Starts from `microchip,rx-burst-reserve`, then follows the
+    // `rx-burst-reserve` private flag.
+    rx_reserve: u16,
+    // Whether the MAC honors received pause frames (MACON1.RXPAUS), set through
+    // ethtool to ride out a partner flooding them
+    rx_pause: bool,
+    // Whether `update_flow_control` may pause the link partner (MACON1.TXPAUS),
+    // off for switches that misbehave with pause frames
+    tx_pause: bool,
+    // Reception stopped through the debugfs `rx_freeze` file, leaving the RX
+    // FIFO and its pointers untouched until resumed
+    rx_frozen: bool,
+    // RX interrupt coalescing set through ethtool. After a run that received
+    // frames PKTIE stays off for `rx_coalesce_usecs`, or until the IRQ work
+    // finds `rx_coalesce_frames` frames pending when it runs for another event.
+    rx_coalesce_usecs: u32,
+    rx_coalesce_frames: u32,
+    rx_deferred: bool,
+    // PHY register being read continuously in MII scan mode
+    phy_scan: Option<PhyRegister>,
+    link_up: bool,
+    // PHSTAT2.DPXSTAT as of the last link-up
+    full_duplex: bool,
+    // Duplex programmed into the MAC and PHY by `init_hardware`, changed
+    // through ethtool. Full duplex unless a half-duplex hub is attached.
+    force_full_duplex: bool,
+    // Peers are to be notified of a link-up once the driver lock is released
+    notify_peers: bool,
+    // ktime of the last carrier change since the interface came up
+    link_changed: Option<i64>,
+    // Only changed by `apply_mtu` together with everything derived from it
+    mtu: u32,
+    // Largest untagged frame including FCS accepted for `mtu`. VLAN-tagged
+    // frames may be `VLAN_HLEN` longer, see `max_tagged_len`.
+    max_frame_len: u16,
+    // `WAKE_*` options set through ethtool
+    wol: u32,
+    // Whether the IRQ is armed as a wake source while suspended
+    wol_armed: bool,
+    // Between `device_suspend` and `device_resume` of a running interface.
+    // The IRQ work leaves the chip alone then, the netdev is detached.
+    suspended: bool,
+    // TXRTS was set for the head of `TxQueue` and its TXIF/TXERIF is still
+    // to come. Cleared along with the TX FIFO.
+    tx_in_flight: bool,
+    // Attempts repeated for the frame in flight after a late collision
+    tx_retries: u8,
+    // ETXST/ETXND have been set up since the last reset. The reset values
+    // don't describe our TX FIFO, so nothing may be transmitted before.
+    tx_fifo_ready: bool,
+    // Programmed by `init_hardware` and `set_rx_filter`
+    rx_filter: RxFilter,
+    // Split of the 8 KB buffer, moved through ethtool ringparam and
+    // programmed by `init_hardware`
+    rx_fifo: FifoRange,
+    tx_fifo: FifoRange,
+    // Received broadcast frames, which `net_device_stats` has no counter for
+    rx_broadcast: u64,
+    // Received pause frames, whether or not `rx_pause` honors them
+    rx_pause_frames: u64,
+    // Received frames with our own source address, only counted in debug builds
+    rx_own_src: u64,
+    // When the last RX error was logged and how many weren't since, see
+    // `rx_err_ratelimit`
+    rx_err_logged: Option<i64>,
+    rx_err_suppressed: u32,
+    // `NETIF_MSG_*` classes logged, set through ethtool
+    msg_enable: u32,
+    // ECON1.BSEL changes, each costing two SPI transactions
+    bank_switches: u64,
+    // Workarounds for the revision found by `check_revision`, see `errata`
+    errata: u8,
+    // Jabber conditions latched in PHSTAT1.JBSTAT
+    phy_jabbers: u64,
+    // Spare skbs of `max_tagged_len` bytes so that RX doesn't allocate per
+    // frame. Refilled after the IRQ work drains the chip.
+    rx_pool: Vec<ARef<net::SkBuff>>,
+    // Sized for the largest frame any MTU allows. TX uses two bytes for the
+    // opcode and the control byte, RX up to the RSV size.
+    // Shared by RX and TX staging. Only reachable through `&mut self`, that is
+    // with `Enc28j60Adapter::driver` locked, so the borrow checker already
+    // rules out concurrent use. A transfer moved out of the lock has to bring
+    // its own buffer.
+    xfer_buf: [u8; 4 + VLAN_MAX_FRAME_LEN as usize],
+    // Powers the chip when the board has it switchable. Declared last so the
+    // chip stays powered until everything else using it has been dropped.
+    vdd: Option<Regulator>,
 }
 
 impl Enc28j60Driver {
@@ -42,41 +622,155 @@ impl Enc28j60Driver {
         self.netdev_reg.as_ref().unwrap().dev_get()
     }
 
+    // Register accesses with the `microchip,spi-retries` of the board
+    fn bus(&self) -> RetryBus<'_> {
+        RetryBus {
+            bus: &self.spidev,
+            retries: self.config.spi_retries,
+        }
+    }
+
     fn switch_bank<T: Register>(&mut self, reg: T) -> Result {
         match reg.bank() {
-            Some(bank) if self.bank != bank => {
-                ECON1.write(&self.spidev, Command::Bfc, econ1::BSEL1 | econ1::BSEL0)?;
-                ECON1.write(&self.spidev, Command::Bfs, bank as _)?;
-                self.bank = bank;
+            Some(bank) => self.select_bank(bank),
+            None => Ok(()),
+        }
+    }
+
+    fn select_bank(&mut self, bank: Bank) -> Result {
+        if self.bank != bank {
+            ECON1.write(&self.bus(), Command::Bfc, econ1::BSEL1 | econ1::BSEL0)?;
+            ECON1.write(&self.bus(), Command::Bfs, bank as _)?;
+            self.bank = bank;
+            self.bank_switches += 1;
+
+            // Catches ECON1 writes that changed BSEL without going through here
+            if cfg!(debug_assertions) {
+                let tracked = self.bank;
+                self.resync_bank()?;
+                if self.bank != tracked {
+                    dev_warn!(
+                        from_dev(&self.spidev),
+                        "bank out of sync: {:?} selected, {:?} in ECON1\n",
+                        tracked,
+                        self.bank
+                    );
+                }
             }
-            _ => {}
         }
         Ok(())
     }
 
+    // Takes the bank from ECON1 after something other than `select_bank`,
+    // such as a reset, changed BSEL
+    fn resync_bank(&mut self) -> Result {
+        let bsel = ECON1.read(&self.bus(), Command::Rcr)? & (econ1::BSEL1 | econ1::BSEL0);
+        self.bank = Bank::from_index(bsel).ok_or(EIO)?;
+        Ok(())
+    }
+
     fn read<T: Register>(&mut self, reg: T) -> Result<T::Size> {
         self.switch_bank(reg)?;
-        reg.read(&self.spidev, Command::Rcr)
+        reg.read(&self.bus(), Command::Rcr)
+    }
+
+    fn read_stable(&mut self, reg: ControlRegisterU16) -> Result<u16> {
+        self.switch_bank(reg)?;
+        reg.read_stable(&self.bus())
     }
 
     fn write<T: Register>(&mut self, reg: T, command: Command, data: T::Size) -> Result {
         self.switch_bank(reg)?;
-        reg.write(&self.spidev, command, data)
+        reg.write(&self.bus(), command, data)
+    }
+
+    // Sets `set` and clears `clear` in `reg`, atomically with BFS/BFC for ETH
+    // registers and by read-modify-write for MAC/MII ones
+    fn modify<T: Register>(&mut self, reg: T, set: T::Size, clear: T::Size) -> Result {
+        let none = T::Size::default();
+        if reg.is_eth() {
+            if clear != none {
+                self.write(reg, Command::Bfc, clear)?;
+            }
+            if set != none {
+                self.write(reg, Command::Bfs, set)?;
+            }
+            Ok(())
+        } else {
+            let value = self.read(reg)?;
+            self.write(reg, Command::Wcr, (value & !clear) | set)
+        }
+    }
+
+    // Sets and immediately clears `bits` of an ETH register
+    fn pulse_bit(&mut self, reg: ControlRegisterU8, bits: u8) -> Result {
+        self.toggle_reset(reg, bits, None)
     }
 
+    // Holds `bits` of an ETH register set for `delay`, e.g. to reset a logic block
+    fn toggle_reset(
+        &mut self,
+        reg: ControlRegisterU8,
+        bits: u8,
+        delay: Option<Duration>,
+    ) -> Result {
+        self.write(reg, Command::Bfs, bits)?;
+        if let Some(delay) = delay {
+            kernel::delay::coarse_sleep(delay);
+        }
+        self.write(reg, Command::Bfc, bits)
+    }
+
+    // Buffer memory transfers are retried along with setting ERDPT/EWRPT, as
+    // a failed one may have moved it. So every RBM and WBM starts at an
+    // explicit address, none continues from where the previous one left off.
     fn read_buffer(&mut self, addr: u16, rx_buf: &mut [u8]) -> Result {
-        self.write(ERDPT, Command::Wcr, addr)?;
+        self.switch_bank(ERDPT)?;
+        let bus: &dyn SpiBus = &self.spidev;
+        retry_transfer(self.config.spi_retries, || {
+            ERDPT.write(bus, Command::Wcr, addr)?;
+            bus.write_then_read(&[Command::Rbm as _], rx_buf)
+        })
+    }
 
-        let tx_buf = [Command::Rbm as _];
-        self.spidev.write_then_read(&tx_buf, rx_buf)
+    // `read_buffer` into the start of `xfer_buf`
+    fn read_xfer_buf(&mut self, addr: u16, len: usize) -> Result {
+        self.switch_bank(ERDPT)?;
+        let bus: &dyn SpiBus = &self.spidev;
+        let rx_buf = &mut self.xfer_buf[..len];
+        retry_transfer(self.config.spi_retries, || {
+            ERDPT.write(bus, Command::Wcr, addr)?;
+            bus.write_then_read(&[Command::Rbm as _], rx_buf)
+        })
     }
 
-    fn write_buffer(&mut self, tx_buf: &[u8]) -> Result {
+    fn write_buffer(&mut self, addr: u16, tx_buf: &[u8]) -> Result {
+        self.switch_bank(EWRPT)?;
         let buf = &mut self.xfer_buf[..tx_buf.len() + 1];
         buf[0] = Command::Wbm as _;
         buf[1..].copy_from_slice(&tx_buf);
 
-        self.spidev.write(buf)
+        let bus: &dyn SpiBus = &self.spidev;
+        retry_transfer(self.config.spi_retries, || {
+            EWRPT.write(bus, Command::Wcr, addr)?;
+            bus.write(buf)
+        })
+    }
+
+    // Writes the per-packet control byte and the frame in a single WBM, as
+    // ECON2.AUTOINC moves EWRPT on from one to the other
+    fn write_tx_buffer(&mut self, addr: u16, control: u8, frame: &[u8]) -> Result {
+        self.switch_bank(EWRPT)?;
+        let buf = &mut self.xfer_buf[..frame.len() + 2];
+        buf[0] = Command::Wbm as _;
+        buf[1] = control;
+        buf[2..].copy_from_slice(frame);
+
+        let bus: &dyn SpiBus = &self.spidev;
+        retry_transfer(self.config.spi_retries, || {
+            EWRPT.write(bus, Command::Wcr, addr)?;
+            bus.write(buf)
+        })
     }
 
     fn read_tsv(&mut self) -> Result<TxStatusVector> {
@@ -87,18 +781,110 @@ impl Enc28j60Driver {
         Ok(TxStatusVector::new(&tsv))
     }
 
+    // Queues the failure on the error queue of the sending socket, if any, so
+    // that `MSG_ERRQUEUE` readers learn about it. `ee_info` holds the raw TSV
+    // status bits.
+    fn report_tx_error(skb: &net::SkBuff, tsv: &TxStatusVector) {
+        let errno =
+            if tsv.status(TsvStatus::LateCollision) || tsv.status(TsvStatus::ExcessiveCollision) {
+                bindings::ECOMM
+            } else if tsv.status(TsvStatus::ExcessiveDefer) {
+                bindings::ETIMEDOUT
+            } else {
+                bindings::EIO
+            };
+
+        let skb = raw_skb(skb);
+        // SAFETY: `SkBuff` wraps a valid `sk_buff` which we hold a reference
+        // to. The clone is owned by us until it's queued or freed, and its
+        // control buffer is large enough for `sock_exterr_skb`.
+        unsafe {
+            let sk = (*skb).sk;
+            if sk.is_null() {
+                return;
+            }
+
+            let clone = bindings::skb_clone_sk(skb);
+            if clone.is_null() {
+                return;
+            }
+
+            let serr = (*clone).cb.as_mut_ptr() as *mut bindings::sock_exterr_skb;
+            core::ptr::write_bytes(serr, 0, 1);
+            (*serr).ee.ee_errno = errno;
+            (*serr).ee.ee_origin = bindings::SO_EE_ORIGIN_LOCAL as _;
+            (*serr).ee.ee_info = tsv.status1 as _;
+
+            if bindings::sock_queue_err_skb(sk, clone) != 0 {
+                bindings::consume_skb(clone);
+            }
+        }
+    }
+
+    // The PHY is driven directly rather than through phylib. It's internal,
+    // 10BASE-T only and without autonegotiation, so there is nothing for the
+    // PHY state machine to do beyond what `check_link_status` does. Its link
+    // changes arrive through EIR.LINKIF together with the MAC events, and
+    // every MII access needs the driver lock for the bank switches and to
+    // keep the PHSTAT2 scan in `phy_scan` consistent, which an MDIO bus
+    // called from phylib's own context wouldn't hold.
     fn read_phy(&mut self, reg: PhyRegister) -> Result<u16> {
+        // MIRD always holds the latest value of the scanned register
+        if self.phy_scan == Some(reg) {
+            return self.read(MIRD);
+        }
+
+        let scan = self.stop_phy_scan()?;
+
         self.write(MIREGADR, Command::Wcr, reg.addr)?;
         self.write(MICMD, Command::Wcr, micmd::MIIRD)?;
-        self.wait_for_ready(MISTAT, mistat::BUSY, 0)?;
+        self.wait_for_ready(MISTAT, mistat::BUSY, 0, MII_TIMEOUT)?;
         self.write(MICMD, Command::Wcr, 0)?;
-        self.read(MIRD)
+        let data = self.read(MIRD)?;
+
+        if let Some(scan) = scan {
+            self.start_phy_scan(scan)?;
+        }
+        Ok(data)
     }
 
     fn write_phy(&mut self, reg: PhyRegister, data: u16) -> Result {
+        let scan = self.stop_phy_scan()?;
+
         self.write(MIREGADR, Command::Wcr, reg.addr)?;
         self.write(MIWR, Command::Wcr, data)?;
-        self.wait_for_ready(MISTAT, mistat::BUSY, 0)
+        self.wait_for_ready(MISTAT, mistat::BUSY, 0, MII_TIMEOUT)?;
+
+        if let Some(scan) = scan {
+            self.start_phy_scan(scan)?;
+        }
+        Ok(())
+    }
+
+    // In MII scan mode the MAC keeps reading `reg` into MIRD in the background,
+    // so polling it costs a single register read instead of a full MII read.
+    fn start_phy_scan(&mut self, reg: PhyRegister) -> Result {
+        self.stop_phy_scan()?;
+
+        self.write(MIREGADR, Command::Wcr, reg.addr)?;
+        self.write(MICMD, Command::Wcr, micmd::MIISCAN)?;
+        // NVALID clears once the first result is in MIRD
+        self.wait_for_ready(MISTAT, mistat::NVALID, 0, MII_TIMEOUT)?;
+
+        self.phy_scan = Some(reg);
+        Ok(())
+    }
+
+    // No other MII operation may be issued while scanning. Returns the
+    // register that was being scanned.
+    fn stop_phy_scan(&mut self) -> Result<Option<PhyRegister>> {
+        let scan = self.phy_scan.take();
+        if scan.is_some() {
+            self.write(MICMD, Command::Wcr, 0)?;
+            self.wait_for_ready(MISTAT, mistat::BUSY, 0, MII_TIMEOUT)?;
+        }
+
+        Ok(scan)
     }
 
     fn wait_for_ready<T: Register>(
@@ -106,34 +892,109 @@ impl Enc28j60Driver {
         reg: T,
         mask: <T as Register>::Size,
         val: <T as Register>::Size,
+        timeout: Duration,
     ) -> Result {
-        while (self.read(reg)? & mask) != val {
+        // Polled at least once, in 1ms steps
+        for _ in 0..=timeout.as_millis() {
+            if (self.read(reg)? & mask) == val {
+                return Ok(());
+            }
             kernel::delay::coarse_sleep(Duration::from_millis(1));
         }
 
-        Ok(())
+        dev_err!(
+            from_dev(&self.spidev),
+            "register not ready after {}ms\n",
+            timeout.as_millis()
+        );
+        Err(ETIMEDOUT)
     }
 
     fn check_link_status(&mut self) -> Result {
         let phstat2 = self.read_phy(PHSTAT2)?;
 
-        if (phstat2 & phstat2::LSTAT) != 0 {
+        let link_up = (phstat2 & phstat2::LSTAT) != 0;
+        if link_up != self.link_up || self.link_changed.is_none() {
+            self.link_up = link_up;
+            // SAFETY: FFI call without preconditions.
+            self.link_changed = Some(unsafe { bindings::ktime_get() });
+            self.notify_peers = link_up && *notify_peers.read();
+        }
+
+        if link_up {
             self.netdev().netif_carrier_on();
             let duplex = (phstat2 & phstat2::DPXSTAT) != 0;
-            dev_info!(
-                from_dev(&self.spidev),
+            self.full_duplex = duplex;
+            netif_msg!(
+                self,
+                NETIF_MSG_LINK_BIT,
+                dev_info,
                 "link up ({})\n",
                 if duplex { "Full Duplex" } else { "Half Duplex" }
             );
+            self.verify_duplex_consistency()?;
         } else {
-            dev_info!(from_dev(&self.spidev), "link down\n");
+            netif_msg!(self, NETIF_MSG_LINK_BIT, dev_info, "link down\n");
+            self.netdev().netif_carrier_off();
+        }
+
+        Ok(())
+    }
+
+    // PHSTAT1 latches jabber and link loss until it's read, so it's only read
+    // when the PHY raised a link interrupt. A link that went down and came
+    // back up in between is then still reported as a flap.
+    fn handle_link_irq(&mut self) -> Result {
+        let phstat1 = self.read_phy(PHSTAT1)?;
+
+        if phstat1 & phstat1::JBSTAT != 0 {
+            self.phy_jabbers += 1;
+            // SAFETY: FFI call without preconditions.
+            if unsafe { bindings::net_ratelimit() } != 0 {
+                dev_warn!(from_dev(&self.spidev), "jabber detected\n");
+            }
+        }
+
+        if phstat1 & phstat1::LLSTAT == 0 && self.link_up {
+            netif_msg!(
+                self,
+                NETIF_MSG_LINK_BIT,
+                dev_info,
+                "link lost since the last interrupt\n"
+            );
+            self.link_up = false;
             self.netdev().netif_carrier_off();
         }
 
+        self.check_link_status()
+    }
+
+    // The MAC, the PHY and what the PHY reports (and so the driver logs) must
+    // agree on the duplex, or frames are silently lost to collisions or FCS
+    // errors. Only checked in debug builds as it costs three register reads.
+    fn verify_duplex_consistency(&mut self) -> Result {
+        if !cfg!(debug_assertions) {
+            return Ok(());
+        }
+
+        let mac = (self.read(MACON3)? & macon3::FULDPX) != 0;
+        let phy = (self.read_phy(PHCON1)? & phcon1::PDPXMD) != 0;
+        let reported = (self.read_phy(PHSTAT2)? & phstat2::DPXSTAT) != 0;
+        if mac != phy || phy != reported {
+            dev_warn!(
+                from_dev(&self.spidev),
+                "duplex mismatch: MACON3.FULDPX={} PHCON1.PDPXMD={} PHSTAT2.DPXSTAT={}\n",
+                mac,
+                phy,
+                reported
+            );
+        }
+
         Ok(())
     }
 
     fn enable_hardware(&mut self) -> Result {
+        self.rx_deferred = false;
         self.write_phy(PHIE, phie::PGEIE | phie::PLNKIE)?;
 
         self.write(
@@ -149,349 +1010,2867 @@ impl Enc28j60Driver {
 
         self.write(ECON1, Command::Bfs, econ1::RXEN)?;
 
+        // For cheap link polling in `check_link_status`
+        self.start_phy_scan(PHSTAT2)?;
+
         Ok(())
     }
 
     fn disable_hardware(&mut self) -> Result {
         self.write(EIE, Command::Wcr, 0x0)?;
         self.write(ECON1, Command::Bfc, econ1::RXEN)?;
+        self.stop_phy_scan()?;
+        Ok(())
+    }
+
+    // Power-down sequence of the datasheet. The chip only keeps the SPI
+    // interface and buffer contents until `leave_power_save`.
+    fn enter_power_save(&mut self) -> Result {
+        self.write(ECON1, Command::Bfc, econ1::RXEN)?;
+        self.wait_for_ready(ESTAT, estat::RXBUSY, 0, RX_DRAIN_TIMEOUT)?;
+        self.wait_for_ready(ECON1, econ1::TXRTS, 0, TX_DRAIN_TIMEOUT)?;
+
+        self.write_phy(PHCON1, phcon1::PPWRSV)?;
+        self.write(ECON2, Command::Bfs, econ2::VRPS)?;
+        self.write(ECON2, Command::Bfs, econ2::PWRSV)
+    }
+
+    fn leave_power_save(&mut self) -> Result {
+        self.write(ECON2, Command::Bfc, econ2::PWRSV)?;
+        self.wait_for_ready(ESTAT, estat::CLKRDY, estat::CLKRDY, CLKRDY_TIMEOUT)
+    }
+
+    // Keeps receiving only magic packets, whose PKTIF wakes the host through
+    // the IRQ. Otherwise the chip is stopped and put into power save mode.
+    // The magic packet filter matches MAADR, which `set_hw_macaddr` keeps
+    // current, so no separate pattern needs to be programmed.
+    // `device_resume` restores ERXFCON from `rx_filter`, clearing MPEN again.
+    fn prepare_suspend(&mut self) -> Result {
+        if self.irq.is_none() || self.wol & bindings::WAKE_MAGIC == 0 {
+            self.disable_hardware()?;
+            self.enter_power_save()?;
+            // `device_resume` reinitializes the chip from scratch anyway
+            self.power_off();
+            return Ok(());
+        }
+        let irq = self.spidev.get_irq() as _;
+
+        // Frames already in the FIFO keep PKTIF set, which would wake the
+        // host right away. They're dropped once only magic packets are let
+        // in, and the other flags cleared, so that INT only follows a magic
+        // packet from here on.
+        self.write(EIE, Command::Wcr, 0)?;
+        self.write(ERXFCON, Command::Wcr, erxfcon::MPEN)?;
+        self.flush_rx_ring()?;
+        self.write(EIR, Command::Wcr, 0)?;
+        self.write(EIE, Command::Wcr, eie::INTIE | eie::PKTIE)?;
+        // SAFETY: `irq` is requested by us in `request_irq`.
+        let ret = unsafe { bindings::enable_irq_wake(irq) };
+        if ret < 0 {
+            return Err(Error::from_kernel_errno(ret));
+        }
+        self.wol_armed = true;
+
         Ok(())
     }
 
+    // A supply that fails to turn off only costs power, the chip is stopped
+    // or in power save mode by then
+    fn power_off(&mut self) {
+        if let Some(vdd) = &mut self.vdd {
+            if let Err(e) = vdd.disable() {
+                dev_warn!(from_dev(&self.spidev), "Failed to disable vdd: {:?}\n", e);
+            }
+        }
+    }
+
     fn init_hardware(&mut self) -> Result {
-        self.spidev.write(&[Command::Src as u8])?;
-        kernel::delay::coarse_sleep(Duration::from_millis(2));
+        self.bus().write(&[Command::Src as u8])?;
+        // Errata: CLKRDY may not be valid right after a reset, so it's only
+        // polled after a delay. The oscillator start-up time varies though.
+        kernel::delay::coarse_sleep(Duration::from_millis(1));
+        match self.wait_for_ready(ESTAT, estat::CLKRDY, estat::CLKRDY, CLKRDY_TIMEOUT) {
+            Err(e) if e == ETIMEDOUT => return Err(ENODEV),
+            result => result?,
+        }
 
         self.write(ECON1, Command::Wcr, 0x0)?;
-        self.bank = Bank::Bank0;
+        self.resync_bank()?;
+        self.rx_paused = false;
+        self.rx_frozen = false;
+        self.tx_fifo_ready = false;
+        self.phy_scan = None;
 
-        match self.read(EREVID)? {
-            0x0 | 0xff => return Err(ENODEV),
-            _ => {}
-        };
+        self.check_revision()?;
 
         self.write(ECON2, Command::Wcr, econ2::AUTOINC)?;
 
-        self.init_rxfifo(&RXFIFO_INIT)?;
-        self.init_txfifo(&TXFIFO_INIT)?;
+        if let Some(cocon) = self.config.clkout {
+            self.write(ECOCON, Command::Wcr, cocon & ecocon::COCON_MASK)?;
+        }
 
-        self.write(
-            ERXFCON,
-            Command::Wcr,
-            erxfcon::UCEN | erxfcon::CRCEN | erxfcon::BCEN,
-        )?;
+        Self::validate_fifo_layout(&self.rx_fifo, &self.tx_fifo)?;
+        self.init_rxfifo(&self.rx_fifo.clone())?;
+        self.init_txfifo(&self.tx_fifo.clone())?;
+
+        for (reg, value) in EHT.into_iter().zip(self.rx_filter.hash_table) {
+            self.write(reg, Command::Wcr, value)?;
+        }
+        self.write(ERXFCON, Command::Wcr, self.rx_filter.erxfcon)?;
 
+        // PASSALL hands control frames that pass the filters to us as well, so
+        // that pause frames can be counted even with RXPAUS off
+        let rxpaus = if self.rx_pause { macon1::RXPAUS } else { 0 };
+        let txpaus = if self.tx_pause { macon1::TXPAUS } else { 0 };
         self.write(
             MACON1,
             Command::Wcr,
-            macon1::MARXEN | macon1::RXPAUS | macon1::TXPAUS,
+            macon1::MARXEN | macon1::PASSALL | rxpaus | txpaus,
         )?;
 
+        // Inter-packet gaps as recommended by the datasheet for each duplex
+        let fuldpx = if self.force_full_duplex {
+            macon3::FULDPX
+        } else {
+            0
+        };
         self.write(
             MACON3,
             Command::Wcr,
-            macon3::FULDPX | macon3::FRMLNEN | macon3::TXCRCEN | macon3::PADCFG0,
+            fuldpx | macon3::FRMLNEN | macon3::TXCRCEN | macon3::PADCFG0,
         )?;
-        self.write(MAIPG, Command::Wcr, 0x12)?;
-        self.write(MABBIPG, Command::Wcr, 0x15)?;
+        if self.force_full_duplex {
+            self.write(MACON4, Command::Wcr, 0)?;
+            self.write(MAIPG, Command::Wcr, 0x12)?;
+            self.write(MABBIPG, Command::Wcr, 0x15)?;
+        } else {
+            self.write(MACON4, Command::Wcr, macon4::DEFER)?;
+            self.write(MAIPG, Command::Wcr, 0x0c12)?;
+            self.write(MABBIPG, Command::Wcr, 0x12)?;
+        }
 
-        self.write(MAMXFL, Command::Wcr, ETH_MAX_FRAME_LEN)?;
+        self.apply_mtu(self.mtu)?;
 
-        self.write_phy(PHLCON, ENC28J60_LAMPS_MODE)?;
+        self.init_phy()?;
 
-        self.write_phy(PHCON1, phcon1::PDPXMD)?;
-        self.write_phy(PHCON2, 0x0)?;
+        self.apply_reg_overrides()?;
+        self.verify_duplex_consistency()?;
 
-        dev_info!(from_dev(&self.spidev), "Hardware initialized\n");
+        dev_info!(
+            from_dev(&self.spidev),
+            "Hardware initialized, SPI clock {} Hz\n",
+            self.config.spi_speed_hz
+        );
 
         Ok(())
     }
 
-    fn erxrdpt_workaround(next_packet_ptr: u16, range: &FifoRange) -> u16 {
-        next_packet_ptr
-            .checked_sub(1)
-            .and_then(|v| if range.contains(&v) { Some(v) } else { None })
-            .unwrap_or_else(|| *range.end())
+    fn init_phy(&mut self) -> Result {
+        self.write_phy(PHLCON, self.config.phlcon)?;
+
+        // Without HDLDIS a half-duplex PHY loops our own frames back to us
+        if self.force_full_duplex {
+            self.write_phy(PHCON1, phcon1::PDPXMD)?;
+            self.write_phy(PHCON2, 0x0)
+        } else {
+            self.write_phy(PHCON1, 0x0)?;
+            self.write_phy(PHCON2, phcon2::HDLDIS)
+        }
     }
 
-    fn init_rxfifo(&mut self, range: &FifoRange) -> Result {
-        if range.is_empty()
-            || !RXFIFO_INIT.contains(range.start())
-            || !RXFIFO_INIT.contains(range.end())
-        {
+    // Resets the PHY alone, which retrains the link without disturbing the
+    // MAC or the FIFOs. `running` restores the link interrupt as well.
+    fn reset_phy(&mut self, running: bool) -> Result {
+        self.write_phy(PHCON1, phcon1::PRST)?;
+        // PRST clears itself once the reset is done
+        let mut tries = 10;
+        while self.read_phy(PHCON1)? & phcon1::PRST != 0 {
+            if tries == 0 {
+                return Err(ETIMEDOUT);
+            }
+            tries -= 1;
+            kernel::delay::coarse_sleep(Duration::from_millis(1));
+        }
+
+        self.init_phy()?;
+        // Only the PHY registers of the overrides were reset, but writing the
+        // others again is harmless
+        self.apply_reg_overrides()?;
+
+        if running {
+            self.write_phy(PHIE, phie::PGEIE | phie::PLNKIE)?;
+            self.check_link_status()?;
+        }
+
+        Ok(())
+    }
+
+    // Frame bytes (without FCS, which the MAC appends) that fit in the TX FIFO
+    // next to the per-packet control byte and the TSV written after the frame
+    fn tx_capacity(range: &FifoRange) -> u16 {
+        (range.end() - range.start() + 1) - 1 - TxStatusVector::size() as u16
+    }
+
+    // Updates every value derived from the MTU at once: the RX oversize check,
+    // MAMXFL, and the limits of `xfer_buf` and the TX FIFO. The chip has no
+    // VLAN offload, so tagged frames are always allowed their extra 4 bytes
+    // and untagged ones are held to the plain Ethernet limit in
+    // `handle_rx_packet` instead of by MAMXFL.
+    fn apply_mtu(&mut self, mtu: u32) -> Result {
+        let frame_len = mtu + bindings::ETH_HLEN + bindings::ETH_FCS_LEN;
+        let tagged_len = frame_len + bindings::VLAN_HLEN;
+        if tagged_len > self.xfer_buf.len() as u32 - 4
+            || tagged_len - bindings::ETH_FCS_LEN > Self::tx_capacity(&self.tx_fifo) as u32
+        {
+            return Err(EINVAL);
+        }
+
+        self.write(MAMXFL, Command::Wcr, tagged_len as u16)?;
+        self.mtu = mtu;
+        self.max_frame_len = frame_len as u16;
+        // Pooled skbs may be too small for the new limit
+        self.rx_pool.clear();
+
+        Ok(())
+    }
+
+    fn max_tagged_len(&self) -> u16 {
+        self.max_frame_len + bindings::VLAN_HLEN as u16
+    }
+
+    fn apply_reg_overrides(&mut self) -> Result {
+        for i in 0..self.config.reg_overrides.len() {
+            match self.config.reg_overrides[i] {
+                RegOverride::Control(reg, value) => self.write(reg, Command::Wcr, value)?,
+                RegOverride::Phy(reg, value) => self.write_phy(reg, value)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    // `addr` moved on by `len` bytes in the RX ring, wrapping from ERXND to
+    // ERXST as RBM does
+    fn rx_ring_addr(&self, addr: u16, len: usize) -> u16 {
+        let start = *self.rx_fifo.start();
+        let size = (self.rx_fifo.end() - start) as usize + 1;
+        start + (((addr - start) as usize + len) % size) as u16
+    }
+
+    // Frees the RX FIFO up to `next_packet_ptr`
+    fn erxrdpt(&self, next_packet_ptr: u16, range: &FifoRange) -> u16 {
+        if self.errata & errata::ERXRDPT_ODD != 0 {
+            Self::erxrdpt_workaround(next_packet_ptr, range)
+        } else {
+            next_packet_ptr
+        }
+    }
+
+    // Errata #14: ERXRDPT must only be written with odd values. The chip pads
+    // frames so `next_packet_ptr` is always even, which makes the byte before
+    // it odd as well. When `next_packet_ptr` is the ring start, the byte before
+    // it is the ring end, which `validate_fifo_layout` keeps odd.
+    fn erxrdpt_workaround(next_packet_ptr: u16, range: &FifoRange) -> u16 {
+        let erxrdpt = if next_packet_ptr == *range.start() || !range.contains(&next_packet_ptr) {
+            *range.end()
+        } else {
+            next_packet_ptr - 1
+        };
+        debug_assert!(erxrdpt & 1 == 1);
+
+        erxrdpt
+    }
+
+    // Both regions must be ordered, within the 8 KB buffer and disjoint. The
+    // RX ring also has to start even and end odd for `erxrdpt_workaround`.
+    fn validate_fifo_layout(rx: &FifoRange, tx: &FifoRange) -> Result {
+        let in_buffer = |range: &FifoRange| !range.is_empty() && *range.end() < BUFFER_SIZE;
+        let overlap = rx.start() <= tx.end() && tx.start() <= rx.end();
+        let rx_aligned = rx.start() & 1 == 0 && rx.end() & 1 == 1;
+
+        if !in_buffer(rx) || !in_buffer(tx) || overlap || !rx_aligned {
+            return Err(EINVAL);
+        }
+
+        Ok(())
+    }
+
+    fn init_rxfifo(&mut self, range: &FifoRange) -> Result {
+        if range.is_empty() || *range.end() >= BUFFER_SIZE {
+            return Err(EINVAL);
+        }
+
+        self.next_packet_ptr = *range.start();
+
+        self.write(ERXST, Command::Wcr, *range.start())?;
+
+        let erxrdpt = self.erxrdpt(self.next_packet_ptr, range);
+        self.write(ERXRDPT, Command::Wcr, erxrdpt)?;
+
+        self.write(ERXND, Command::Wcr, *range.end())
+    }
+
+    fn init_txfifo(&mut self, range: &FifoRange) -> Result {
+        if range.is_empty() || *range.end() >= BUFFER_SIZE {
+            return Err(EINVAL);
+        }
+
+        self.tx_fifo_ready = false;
+        self.tx_in_flight = false;
+        self.write(ETXST, Command::Wcr, *range.start())?;
+        self.write(ETXND, Command::Wcr, *range.end())?;
+        self.tx_fifo_ready = true;
+
+        Ok(())
+    }
+
+    fn set_random_macaddr(&mut self, netdev: &net::Device) -> Result {
+        netdev.eth_hw_addr_random();
+        self.set_hw_macaddr(netdev)
+    }
+
+    // Uses the address provisioned for the board through `local-mac-address`,
+    // `mac-address` or an nvmem cell. Returns false if there is none.
+    fn set_dt_macaddr(&mut self, netdev: &net::Device) -> Result<bool> {
+        let mut addr = [0u8; bindings::ETH_ALEN as usize];
+        // SAFETY: `of_get_mac_address` accepts a null node, and `addr` has room
+        // for ETH_ALEN bytes.
+        let ret = unsafe { bindings::of_get_mac_address(of_node(&self.spidev), addr.as_mut_ptr()) };
+        if ret == -(bindings::EPROBE_DEFER as i32) {
+            // The nvmem provider hasn't probed yet
+            return Err(Error::from_kernel_errno(ret));
+        }
+        if ret != 0 {
+            return Ok(false);
+        }
+        if !is_valid_ether_addr(&addr) {
+            dev_warn!(
+                from_dev(&self.spidev),
+                "invalid MAC address in the device tree, ignoring it\n"
+            );
+            return Ok(false);
+        }
+
+        netdev.eth_hw_addr_set(&addr);
+        self.set_hw_macaddr(netdev)?;
+        Ok(true)
+    }
+
+    // Hashes the serial number of the board (the `serial-number` property of
+    // the DT root) together with the SPI device name, so the address stays the
+    // same across reboots and module reloads but differs between boards.
+    // Falls back to a random address on boards without a serial number.
+    fn set_stable_macaddr(&mut self, netdev: &net::Device) -> Result {
+        let mut serial: *const core::ffi::c_char = core::ptr::null();
+        // SAFETY: `of_root` is either null or the valid root node, and `serial`
+        // is only written on success.
+        let ret = unsafe {
+            bindings::of_property_read_string(
+                bindings::of_root,
+                c_str!("serial-number").as_char_ptr(),
+                &mut serial,
+            )
+        };
+        if ret != 0 {
+            dev_warn!(
+                from_dev(&self.spidev),
+                "no board serial number, using a random MAC address\n"
+            );
+            return self.set_random_macaddr(netdev);
+        }
+
+        // SAFETY: `serial` points to the NUL-terminated property value of the
+        // root node, which is never freed.
+        let serial = unsafe { CStr::from_char_ptr(serial) };
+        let name = self.spidev.name();
+
+        let crc = |crc: u32, data: &[u8]| {
+            // SAFETY: `data` is a valid slice.
+            unsafe { bindings::crc32_le(crc, data.as_ptr(), data.len()) }
+        };
+        let high = crc(crc(!0, serial.as_bytes()), name.as_bytes());
+        let low = crc(high, name.as_bytes()).to_be_bytes();
+        let high = high.to_be_bytes();
+
+        // Unicast and locally administered
+        let addr = [
+            (high[0] & !0x01) | 0x02,
+            high[1],
+            high[2],
+            high[3],
+            low[0],
+            low[1],
+        ];
+        netdev.eth_hw_addr_set(&addr);
+        self.set_hw_macaddr(netdev)
+    }
+
+    fn set_rx_filter(&mut self, filter: RxFilter) -> Result {
+        if filter == self.rx_filter {
+            return Ok(());
+        }
+
+        if filter.hash_table != self.rx_filter.hash_table {
+            for (reg, value) in EHT.into_iter().zip(filter.hash_table) {
+                self.write(reg, Command::Wcr, value)?;
+            }
+        }
+        self.write(ERXFCON, Command::Wcr, filter.erxfcon)?;
+        self.rx_filter = filter;
+
+        Ok(())
+    }
+
+    // A floating or shorted MISO reads as all zeros or ones
+    fn check_revision(&mut self) -> Result {
+        let erevid = self.read(EREVID)?;
+        if erevid == 0x0 || erevid == 0xff {
+            return Err(ENODEV);
+        }
+
+        self.errata = Revision::errata(Revision::from_erevid(erevid));
+        Ok(())
+    }
+
+    // Sends a frame through the PHY loopback and checks that it's received
+    // unchanged. Must only run while the interface is down, as the chip is
+    // reset around the test and left stopped.
+    fn test_loopback(&mut self) -> Result {
+        // Sent to broadcast, which the receive filter is set to let in for
+        // the test. Ethertype 0x88b5 is reserved for local experiments.
+        let mut frame = [0u8; ETH_MIN_FRAME_LEN as usize - bindings::ETH_FCS_LEN as usize];
+        frame[..6].fill(0xff);
+        frame[6..12].copy_from_slice(&[0x02, 0, 0, 0, 0, 0x01]);
+        frame[12..14].copy_from_slice(&0x88b5u16.to_be_bytes());
+        for (i, b) in frame[14..].iter_mut().enumerate() {
+            *b = i as u8;
+        }
+
+        // PHY loopback only works in full duplex
+        let force_full_duplex = core::mem::replace(&mut self.force_full_duplex, true);
+        let result = (|| -> Result {
+            self.init_hardware()?;
+            self.write_phy(PHCON1, phcon1::PDPXMD | phcon1::PLOOPBK)?;
+            self.write(ERXFCON, Command::Wcr, erxfcon::CRCEN | erxfcon::BCEN)?;
+            self.write(ECON1, Command::Bfs, econ1::RXEN)?;
+            self.loopback_frame(&frame)
+        })();
+
+        // Also restores the receive filter
+        self.force_full_duplex = force_full_duplex;
+        self.init_hardware()?;
+        result
+    }
+
+    fn loopback_frame(&mut self, frame: &[u8]) -> Result {
+        self.start_tx(frame, None)?;
+        self.wait_for_ready(ECON1, econ1::TXRTS, 0, TX_DRAIN_TIMEOUT)?;
+
+        // The frame is back right after TX completes, so this is plenty
+        let mut tries = 10;
+        while self.read(EPKTCNT)? == 0 {
+            if tries == 0 {
+                return Err(ETIMEDOUT);
+            }
+            tries -= 1;
+            kernel::delay::coarse_sleep(Duration::from_millis(1));
+        }
+
+        let rsv_size = RxStatusVector::size();
+        let len = rsv_size + frame.len();
+        self.read_xfer_buf(self.next_packet_ptr, len)?;
+
+        let mut rsv = [0; RxStatusVector::size()];
+        rsv.copy_from_slice(&self.xfer_buf[..rsv_size]);
+        let rsv = RxStatusVector::new(&rsv);
+        if !rsv.status(RsvStatus::RxOk)
+            || rsv.byte_count as u32 != frame.len() as u32 + bindings::ETH_FCS_LEN
+            || self.xfer_buf[rsv_size..len] != *frame
+        {
+            return Err(EIO);
+        }
+
+        Ok(())
+    }
+
+    // Every path that resets the chip goes through here, so the reset never
+    // loses configuration. Everything kept in the driver is written back: the
+    // MAC/PHY defaults, the MTU and the DT register overrides in
+    // `init_hardware`, then the address the netdev currently has.
+    fn reset_and_reconfigure(&mut self, netdev: &net::Device) -> Result {
+        self.disable_hardware()?;
+        self.init_hardware()?;
+        self.set_hw_macaddr(netdev)?;
+        self.enable_hardware()
+    }
+
+    fn set_hw_macaddr(&mut self, netdev: &net::Device) -> Result {
+        let dev_addr = netdev.device_address();
+        if !is_valid_ether_addr(&dev_addr[..bindings::ETH_ALEN as usize]) {
+            return Err(EADDRNOTAVAIL);
+        }
+
+        self.write_maadr(&dev_addr[..bindings::ETH_ALEN as usize])
+    }
+
+    fn write_maadr(&mut self, addr: &[u8]) -> Result {
+        dev_info!(
+            from_dev(&self.spidev),
+            "MAC address: {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}\n",
+            addr[0],
+            addr[1],
+            addr[2],
+            addr[3],
+            addr[4],
+            addr[5]
+        );
+
+        for (reg, addr) in [MAADR1, MAADR2, MAADR3, MAADR4, MAADR5, MAADR6]
+            .iter()
+            .zip(addr)
+        {
+            self.write(*reg, Command::Wcr, *addr)?;
+        }
+
+        Ok(())
+    }
+
+    // Free bytes in the RX FIFO as given in the datasheet
+    fn rx_fifo_free(&mut self) -> Result<u16> {
+        // Flow control decisions must not be based on a torn ERXWRPT
+        let wrpt = self.read_stable(ERXWRPT)?;
+        let rdpt = self.read(ERXRDPT)?;
+        let size = self.rx_fifo.end() - self.rx_fifo.start();
+
+        Ok(match wrpt.cmp(&rdpt) {
+            core::cmp::Ordering::Greater => size - (wrpt - rdpt),
+            core::cmp::Ordering::Equal => size,
+            core::cmp::Ordering::Less => rdpt - wrpt - 1,
+        })
+    }
+
+    // Bytes received but not freed through ERXRDPT yet, i.e. ERXWRPT - ERXRDPT
+    // modulo the ring size. One byte of the ring is never usable, as ERXRDPT
+    // trails the next packet, so used and free add up to one less than it.
+    fn rx_fifo_used(&mut self) -> Result<u16> {
+        let free = self.rx_fifo_free()?;
+        Ok(self.rx_fifo.end() - self.rx_fifo.start() - free)
+    }
+
+    // The chip has no flow-control watermark, so pause the link partner from
+    // software once the free RX space drops below the configured reserve and
+    // release it when the FIFO has been drained. A larger reserve tolerates
+    // longer bursts on a slow host at the cost of pausing the sender earlier.
+    fn update_flow_control(&mut self) -> Result {
+        // A link partner paused when the reserve was turned off is released
+        if self.rx_reserve == 0 && !self.rx_paused {
+            return Ok(());
+        }
+
+        let paused =
+            self.rx_reserve != 0 && self.tx_pause && self.rx_fifo_free()? < self.rx_reserve;
+        if paused == self.rx_paused {
+            return Ok(());
+        }
+
+        let full_duplex = (self.read(EFLOCON)? & eflocon::FULDPXS) != 0;
+        let fcen = match (paused, full_duplex) {
+            (true, true) => eflocon::FCEN1,
+            (false, true) => eflocon::FCEN1 | eflocon::FCEN0,
+            (true, false) => eflocon::FCEN0,
+            (false, false) => 0,
+        };
+        self.write(EFLOCON, Command::Wcr, fcen)?;
+        self.rx_paused = paused;
+
+        Ok(())
+    }
+
+    // Drops every frame in the RX FIFO. EPKTCNT goes to zero, which also
+    // clears PKTIF.
+    fn flush_rx_ring(&mut self) -> Result {
+        self.write(ECON1, Command::Bfc, econ1::RXEN)?;
+        self.wait_for_ready(ESTAT, estat::RXBUSY, 0, RX_DRAIN_TIMEOUT)?;
+        // Read with reception stopped, so that no frame is left counted
+        let pending = self.read(EPKTCNT)?;
+        self.pulse_bit(ECON1, econ1::RXRST)?;
+        self.init_rxfifo(&self.rx_fifo.clone())?;
+        for _ in 0..pending {
+            self.write(ECON2, Command::Bfs, econ2::PKTDEC)?;
+        }
+        self.update_stats(|stats| stats.rx_dropped += pending as c_ulong);
+        // Releases the link partner now that the FIFO is empty
+        self.update_flow_control()?;
+
+        self.write(ECON1, Command::Bfs, econ1::RXEN)
+    }
+
+    // `frame` must fit in `tx_capacity`. Completion is signaled by TXIF/TXERIF.
+    // `csum` is the checksum to fill in as given by `skb_csum_partial`.
+    fn start_tx(&mut self, frame: &[u8], csum: Option<(u16, u16)>) -> Result {
+        if self.errata & errata::TX_LOGIC_RESET != 0 && !self.full_duplex {
+            self.pulse_bit(ECON1, econ1::TXRST)?;
+        }
+
+        let txst = *self.tx_fifo.start();
+        self.write(ETXND, Command::Wcr, txst + frame.len() as u16)?;
+
+        // The per-packet control byte, 0 to use the MACON3 settings
+        self.write_tx_buffer(txst, 0, frame)?;
+
+        if let Some((start, offset)) = csum {
+            self.insert_tx_csum(start, offset, frame.len() as u16)?;
+        }
+
+        self.write(ECON1, Command::Bfs, econ1::TXRTS)?;
+        self.tx_in_flight = true;
+
+        Ok(())
+    }
+
+    // Has the DMA checksum the frame in the TX FIFO from `start` to its end.
+    // The stack has put the pseudo-header sum at `start + offset`, so the
+    // result only needs to be written over it.
+    fn insert_tx_csum(&mut self, start: u16, offset: u16, len: u16) -> Result {
+        if start + offset + 2 > len {
+            return Err(EINVAL);
+        }
+
+        // The frame follows the per-packet control byte
+        let base = self.tx_fifo.start() + 1;
+        self.write(EDMAST, Command::Wcr, base + start)?;
+        self.write(EDMAND, Command::Wcr, base + len - 1)?;
+        self.write(ECON1, Command::Bfs, econ1::CSUMEN | econ1::DMAST)?;
+        let result = self.wait_for_ready(ECON1, econ1::DMAST, 0, DMA_TIMEOUT);
+        self.write(ECON1, Command::Bfc, econ1::CSUMEN)?;
+        result?;
+
+        let mut csum = self.read(EDMACS)?;
+        if csum == 0 && offset == UDP_CSUM_OFFSET {
+            csum = 0xffff;
+        }
+        self.write_buffer(base + start + offset, &csum.to_be_bytes())
+    }
+
+    // Recovers from an RX FIFO overflow or a corrupted RSV, after which the
+    // ring pointers can't be trusted, the same way TXERIF resets the TX FIFO.
+    // The frames still in the FIFO are dropped.
+    fn reset_rx_ring(&mut self) -> Result {
+        let wrpt = self.read(ERXWRPT)?;
+        let pending = self.read(EPKTCNT)?;
+        dev_warn!(
+            from_dev(&self.spidev),
+            "RX ring reset: ERXWRPT {:#06x}, next packet {:#06x}, {} pending\n",
+            wrpt,
+            self.next_packet_ptr,
+            pending
+        );
+
+        self.flush_rx_ring()
+    }
+
+    // Receives up to `budget` frames and returns how many there were
+    fn handle_rx(&mut self, stats: &Stats, budget: u8) -> Result<u8> {
+        let packet_count = self.read(EPKTCNT)?.min(budget);
+
+        for i in 0..packet_count {
+            if !self.handle_rx_packet(stats)? {
+                // The ring was reset, which dropped the rest
+                return Ok(i + 1);
+            }
+            self.write(ECON2, Command::Bfs, econ2::PKTDEC)?;
+        }
+
+        Ok(packet_count)
+    }
+
+    // Error counters live in `net_device_stats`, which `get_stats64` reports
+    // along with `Stats`. Only updated with the driver lock held.
+    fn update_stats(&mut self, update: impl FnOnce(&mut bindings::net_device_stats)) {
+        let netdev = self.netdev();
+        // SAFETY: The netdev is registered and `&mut self` means the driver
+        // lock is held, which serializes every update.
+        update(unsafe { &mut (*raw_netdev(&netdev)).stats });
+    }
+
+    fn netdev_stat(&self, get: impl FnOnce(&bindings::net_device_stats) -> c_ulong) -> u64 {
+        let netdev = self.netdev();
+        // SAFETY: The netdev is registered and `update_stats` only writes with
+        // the driver lock held, which `&self` implies.
+        get(unsafe { &(*raw_netdev(&netdev)).stats }) as u64
+    }
+
+    // Every counter is updated with the driver lock held, which the caller has
+    fn reset_stats(&mut self, netdev: &net::Device) {
+        // SAFETY: `netdev` is registered and `net_device_stats` is plain counters.
+        unsafe { core::ptr::write_bytes(&mut (*raw_netdev(netdev)).stats, 0, 1) };
+        self.rx_broadcast = 0;
+        self.rx_pause_frames = 0;
+        self.rx_own_src = 0;
+        self.bank_switches = 0;
+        self.phy_jabbers = 0;
+    }
+
+    // Allocation failures are left to the fallback in `handle_rx_packet`
+    fn refill_rx_pool(&mut self) {
+        let netdev = self.netdev();
+        while self.rx_pool.len() < RX_POOL_SIZE {
+            match netdev.alloc_skb_ip_align(self.max_tagged_len() as _) {
+                // Never fails as the capacity is reserved in `try_new`
+                Ok(skb) => {
+                    let _ = self.rx_pool.try_push(skb);
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    // An RSV that can't have come from the chip, e.g. after an SPI glitch.
+    // Following its next packet pointer would read garbage from then on.
+    // Packets always start at even addresses. A short byte count is a runt
+    // rather than corruption, see `handle_rx_packet`.
+    fn rsv_corrupted(rsv: &RxStatusVector, rx_fifo: &FifoRange) -> bool {
+        rsv.byte_count > VLAN_MAX_FRAME_LEN
+            || rsv.next_ptr % 2 != 0
+            || !rx_fifo.contains(&rsv.next_ptr)
+    }
+
+    fn rx_limit(&self, rsv: &RxStatusVector) -> u16 {
+        if rsv.status(RsvStatus::RxTypeVlan) {
+            self.max_tagged_len()
+        } else {
+            self.max_frame_len
+        }
+    }
+
+    // Whether an RX error may be logged. The first one always is, then one
+    // per `RX_ERR_LOG_INTERVAL` along with the count of those left out.
+    // The error counters are updated regardless.
+    fn rx_err_ratelimit(&mut self) -> bool {
+        // SAFETY: FFI call without preconditions.
+        let now = unsafe { bindings::ktime_get() };
+        if let Some(logged) = self.rx_err_logged {
+            if now - logged < RX_ERR_LOG_INTERVAL.as_nanos() as i64 {
+                self.rx_err_suppressed += 1;
+                return false;
+            }
+        }
+
+        let suppressed = core::mem::take(&mut self.rx_err_suppressed);
+        if suppressed != 0 {
+            netif_msg!(
+                self,
+                NETIF_MSG_RX_ERR_BIT,
+                dev_err,
+                "{} RX errors not logged\n",
+                suppressed
+            );
+        }
+        self.rx_err_logged = Some(now);
+        true
+    }
+
+    // Returns false if the RSV was corrupted and the ring had to be reset
+    fn handle_rx_packet(&mut self, stats: &Stats) -> Result<bool> {
+        // Speculatively read the head of the frame along with the RSV, which
+        // saves a transaction for frames that fit in it.
+        // Frames may wrap around the end of the RX FIFO. No split reads are
+        // needed for that: with ECON2.AUTOINC, RBM moves ERDPT from ERXND back
+        // to ERXST by itself, so a single read follows the ring.
+        let rsv_size = RxStatusVector::size();
+        let head_len = rsv_size + self.rx_single_read_max as usize;
+        self.read_xfer_buf(self.next_packet_ptr, head_len)?;
+
+        let mut rsv = [0; RxStatusVector::size()];
+        rsv.copy_from_slice(&self.xfer_buf[..rsv_size]);
+        let rsv = RxStatusVector::new(&rsv);
+
+        if Self::rsv_corrupted(&rsv, &self.rx_fifo) {
+            if self.rx_err_ratelimit() {
+                netif_msg!(
+                    self,
+                    NETIF_MSG_RX_ERR_BIT,
+                    dev_err,
+                    "RX failed: corrupted RSV {:?} at {:#06x}\n",
+                    rsv,
+                    self.next_packet_ptr
+                );
+            }
+            self.update_stats(|stats| stats.rx_errors += 1);
+            self.reset_rx_ring()?;
+            return Ok(false);
+        }
+
+        if rsv.byte_count < ETH_MIN_FRAME_LEN {
+            // A runt, or a zero byte count, with a sane next packet pointer.
+            // Only this frame is skipped, the rest of the ring is fine.
+            if self.rx_err_ratelimit() {
+                netif_msg!(
+                    self,
+                    NETIF_MSG_RX_ERR_BIT,
+                    dev_err,
+                    "RX failed: runt frame {:?}\n",
+                    rsv
+                );
+            }
+            self.update_stats(|stats| {
+                stats.rx_errors += 1;
+                stats.rx_length_errors += 1;
+            });
+        } else if rsv.status(RsvStatus::RxControlFrame) {
+            // Only received due to PASSALL, the stack has no use for them
+            if rsv.status(RsvStatus::RxPauseFrame) {
+                self.rx_pause_frames += 1;
+            }
+        } else if !rsv.status(RsvStatus::RxOk) || rsv.byte_count > self.rx_limit(&rsv) {
+            if self.rx_err_ratelimit() {
+                netif_msg!(
+                    self,
+                    NETIF_MSG_RX_ERR_BIT,
+                    dev_err,
+                    "RX failed: {:?} Crc={} LengthCheckError={}\n",
+                    rsv,
+                    rsv.status(RsvStatus::CrcError),
+                    rsv.status(RsvStatus::LengthCheckError)
+                );
+            }
+            let oversized = rsv.byte_count > self.rx_limit(&rsv);
+            self.update_stats(|stats| {
+                stats.rx_errors += 1;
+                if rsv.status(RsvStatus::CrcError) {
+                    stats.rx_crc_errors += 1;
+                }
+                if rsv.status(RsvStatus::LengthCheckError)
+                    || rsv.status(RsvStatus::LengthOutOfRange)
+                    || oversized
+                {
+                    stats.rx_length_errors += 1;
+                }
+            });
+        } else {
+            let netdev = self.netdev();
+            // The RSV byte count includes the FCS, which is only kept with
+            // NETIF_F_RXFCS (`ethtool -K rx-fcs on`). Then it's just not read,
+            // as the next frame is found through `rsv.next_ptr`.
+            // SAFETY: `netdev` is registered. `features` changes under RTNL,
+            // a frame seeing the old value during a toggle is fine.
+            let features = unsafe { (*raw_netdev(&netdev)).features };
+            let len = if features & (1 << bindings::NETIF_F_RXFCS_BIT) != 0 {
+                rsv.byte_count
+            } else {
+                rsv.byte_count - bindings::ETH_FCS_LEN as u16
+            };
+            let skb = match self.rx_pool.pop() {
+                Some(skb) => skb,
+                None => match netdev.alloc_skb_ip_align(len as _) {
+                    Ok(skb) => skb,
+                    // Bailing out would leave the frame in the ring to fail
+                    // again on every run, so it's skipped like any other
+                    Err(_) => {
+                        self.update_stats(|stats| stats.rx_dropped += 1);
+                        self.advance_rx_ring(rsv.next_ptr)?;
+                        return Ok(true);
+                    }
+                },
+            };
+            let room = skb.put(len as _);
+
+            let buffered = room.len().min(head_len - rsv_size);
+            room[..buffered].copy_from_slice(&self.xfer_buf[rsv_size..rsv_size + buffered]);
+
+            // Each chunk starts at its own address, so that it can be retried
+            let mut addr = self.rx_ring_addr(self.next_packet_ptr, rsv_size + buffered);
+            for chunk in room[buffered..].chunks_mut(self.rx_chunk_max) {
+                self.read_buffer(addr, chunk)?;
+                addr = self.rx_ring_addr(addr, chunk.len());
+            }
+
+            if rsv.status(RsvStatus::RxMulticast) {
+                self.update_stats(|stats| stats.multicast += 1);
+            } else if rsv.status(RsvStatus::RxBroadcast) {
+                self.rx_broadcast += 1;
+            }
+
+            // Our own address as the source means another host uses it or
+            // our frames loop back. Costs a compare per frame, so debug only.
+            if cfg!(debug_assertions) && room[6..12] == netdev.device_address()[..6] {
+                self.rx_own_src += 1;
+                // SAFETY: FFI call without preconditions.
+                if unsafe { bindings::net_ratelimit() } != 0 {
+                    dev_warn!(
+                        from_dev(&self.spidev),
+                        "received a frame from our own MAC address, duplicate address or loop?\n"
+                    );
+                }
+            }
+
+            skb.set_protocol(skb.eth_type_trans(&netdev));
+            skb_rx_timestamp(&skb);
+            netdev.netif_rx(&skb);
+            stats.rx(len as _);
+        }
+
+        self.advance_rx_ring(rsv.next_ptr)?;
+
+        Ok(true)
+    }
+
+    // Frees the space of the packet just handled for the chip to reuse
+    fn advance_rx_ring(&mut self, next_ptr: u16) -> Result {
+        self.next_packet_ptr = next_ptr;
+        let erxrdpt = self.erxrdpt(next_ptr, &self.rx_fifo);
+        self.write(ERXRDPT, Command::Wcr, erxrdpt)
+    }
+}
+
+// Packet and byte counters readable without the driver lock, which may be
+// held across many SPI transfers. They're still only updated (and reset) with
+// the driver lock held, except for `tx_dropped`.
+#[derive(Default)]
+struct Stats {
+    rx_packets: AtomicU64,
+    rx_bytes: AtomicU64,
+    tx_packets: AtomicU64,
+    tx_bytes: AtomicU64,
+    // Frames `start_xmit` drops, which runs in atomic context
+    tx_dropped: AtomicU64,
+}
+
+impl Stats {
+    fn count(packets: &AtomicU64, bytes: &AtomicU64, len: usize) {
+        packets.fetch_add(1, Ordering::Relaxed);
+        bytes.fetch_add(len as _, Ordering::Relaxed);
+    }
+
+    fn rx(&self, len: usize) {
+        Self::count(&self.rx_packets, &self.rx_bytes, len);
+    }
+
+    fn tx(&self, len: usize) {
+        Self::count(&self.tx_packets, &self.tx_bytes, len);
+    }
+
+    fn reset(&self) {
+        for counter in [
+            &self.rx_packets,
+            &self.rx_bytes,
+            &self.tx_packets,
+            &self.tx_bytes,
+            &self.tx_dropped,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+// Frames handed over by `start_xmit`, oldest first. The chip sends one
+// frame at a time from the head, the others wait here so that the next one
+// is ready as soon as the previous one completes. `start_xmit` stops the
+// netdev queue once `limit` frames, the one in flight included, are queued.
+struct TxQueue {
+    skbs: [Option<ARef<net::SkBuff>>; TX_QUEUE_MAX],
+    head: usize,
+    len: usize,
+    limit: usize,
+}
+
+impl TxQueue {
+    fn new(limit: usize) -> Self {
+        Self {
+            skbs: core::array::from_fn(|_| None),
+            head: 0,
+            len: 0,
+            limit,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_full(&self) -> bool {
+        self.len >= self.limit
+    }
+
+    // Gives `skb` back if the queue is full
+    fn push(&mut self, skb: ARef<net::SkBuff>) -> core::result::Result<(), ARef<net::SkBuff>> {
+        if self.is_full() {
+            return Err(skb);
+        }
+
+        self.skbs[(self.head + self.len) % TX_QUEUE_MAX] = Some(skb);
+        self.len += 1;
+        Ok(())
+    }
+
+    fn front(&self) -> Option<ARef<net::SkBuff>> {
+        self.skbs[self.head].clone()
+    }
+
+    fn pop(&mut self) -> Option<ARef<net::SkBuff>> {
+        let skb = self.skbs[self.head].take()?;
+        self.head = (self.head + 1) % TX_QUEUE_MAX;
+        self.len -= 1;
+        Some(skb)
+    }
+
+    fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+// Receive filter derived from the netdev flags and multicast list
+#[derive(Copy, Clone, PartialEq, Eq)]
+struct RxFilter {
+    erxfcon: u8,
+    // EHT0..EHT7, used with HTEN
+    hash_table: [u8; 8],
+}
+
+impl RxFilter {
+    const DEFAULT: Self = Self {
+        erxfcon: erxfcon::UCEN | erxfcon::CRCEN | erxfcon::BCEN,
+        hash_table: [0; 8],
+    };
+    // Frames with a bad CRC are dropped even when promiscuous. Control
+    // frames reach the driver anyway due to MACON1.PASSALL.
+    const PROMISC: Self = Self {
+        erxfcon: erxfcon::CRCEN,
+        hash_table: [0; 8],
+    };
+
+    // The filter of the `rx_filter` module parameter, which the chip starts
+    // with and `from_netdev` builds on
+    fn from_param(spidev: &spi::Device) -> Self {
+        match &*rx_filter.read() {
+            b"unicast+broadcast" => Self::DEFAULT,
+            b"unicast" => Self {
+                erxfcon: erxfcon::UCEN | erxfcon::CRCEN,
+                hash_table: [0; 8],
+            },
+            b"promiscuous" => Self::PROMISC,
+            _ => {
+                dev_warn!(
+                    from_dev(spidev),
+                    "unknown rx_filter, using unicast+broadcast\n"
+                );
+                Self::DEFAULT
+            }
+        }
+    }
+
+    // Must be called with the netdev address list lock held, as in `set_rx_mode`
+    fn from_netdev(netdev: &net::Device, base: Self) -> Self {
+        let raw = raw_netdev(netdev);
+        // SAFETY: `netdev` is a valid net device.
+        let flags = unsafe { (*raw).flags };
+
+        // Enabling the hash filter would stop accepting everything
+        if flags & bindings::net_device_flags_IFF_PROMISC != 0 || base == Self::PROMISC {
+            return Self::PROMISC;
+        }
+
+        let mut filter = base;
+        if flags & bindings::net_device_flags_IFF_ALLMULTI != 0 {
+            filter.hash_table = [0xff; 8];
+        } else {
+            // SAFETY: The multicast list is stable while its lock is held.
+            unsafe {
+                let head = ptr::addr_of_mut!((*raw).mc.list);
+                let mut pos = (*head).next;
+                while pos != head {
+                    let ha = kernel::container_of!(pos, bindings::netdev_hw_addr, list);
+                    filter.hash(&(*ha).addr[..bindings::ETH_ALEN as usize]);
+                    pos = (*pos).next;
+                }
+            }
+        }
+
+        // Not MCEN, which would accept any multicast frame as the filters
+        // are ORed
+        if filter.hash_table != [0; 8] {
+            filter.erxfcon |= erxfcon::HTEN;
+        }
+
+        filter
+    }
+
+    // The hash is bits 28:23 of the CRC of the destination address, which
+    // selects one of the 64 bits of EHT0..EHT7
+    fn hash(&mut self, addr: &[u8]) {
+        // SAFETY: FFI call without preconditions.
+        let crc = unsafe { bindings::crc32_le(!0, addr.as_ptr(), addr.len()) };
+        let bit = (crc >> 23) & 0x3f;
+        self.hash_table[bit as usize / 8] |= 1 << (bit % 8);
+    }
+}
+
+struct Enc28j60Adapter {
+    driver: Mutex<Enc28j60Driver>,
+    // EREVID and the SPI device name, cached at probe for ethtool
+    revision: u8,
+    bus_info: CString,
+    stats: Stats,
+    workqueue: workqueue::BoxedQueue,
+    irq_work: workqueue::Work,
+    tx_work: workqueue::Work,
+    tx_queue: SpinLock<TxQueue>,
+    // `set_rx_mode` runs in atomic context, so it leaves the filter here for
+    // `RxModeWorkHandler` to program
+    rx_mode_work: workqueue::Work,
+    rx_mode: SpinLock<RxFilter>,
+    // What `set_rx_mode` starts from, see `RxFilter::from_param`
+    rx_filter_base: RxFilter,
+    // `tx_timeout` runs in atomic context as well
+    tx_timeout_work: workqueue::Work,
+    // Services the chip instead of the IRQ when polling, see `poll_interval_us`
+    poll_timer: Opaque<bindings::hrtimer>,
+    poll_interval: i64,
+    // `Arc` reference owned by the running poll timer
+    poll_ref: AtomicPtr<c_void>,
+    // Real-time kthread the poll timer hands the servicing to, see
+    // `create_poll_worker`. Null unless polling.
+    poll_worker: AtomicPtr<bindings::kthread_worker>,
+    poll_work: Opaque<bindings::kthread_work>,
+    // `Arc` reference owned by `poll_work` while it's queued
+    poll_work_ref: AtomicPtr<c_void>,
+    // Ends the RX coalescing delay, see `rx_coalesce_usecs`
+    coalesce_timer: Opaque<bindings::hrtimer>,
+    coalesce_ref: AtomicPtr<c_void>,
+    coalesce_expired: AtomicBool,
+    // `silicon_rev` under the netdev in sysfs, pointing at `revision`
+    rev_attr: Opaque<bindings::dev_ext_attribute>,
+}
+
+// SAFETY:
+//  - `Send` for `workqueue::Work` and `ARef<SkBuff>`.
+//     The types lack Send due to holding raw pointers.
+//     It's safe to send them to other threads.
+//     Dereferencing raw pointers is unsafe anyway.
+//  - `Sync` for `workqueue::Work`.
+//     The type lacks Sync due to holding raw pointers.
+//     `Work` wraps `work_struct` which is a thread-safe type.
+//  - `Send` for `enc28j60_debugfs::Dir`.
+//     The dentry is only passed to debugfs, which does its own locking.
+//  - `Sync` for `Opaque<hrtimer>` and `Opaque<kthread_work>`.
+//     They're only accessed through the hrtimer and kthread worker APIs,
+//     which are thread-safe.
+//  - `Send` for `Regulator`.
+//     The regulator API may be called from any thread.
+//  - `Sync` for `Opaque<dev_ext_attribute>`.
+//     It's only written before the sysfs file is created, then read by sysfs.
+unsafe impl Send for Enc28j60Adapter {}
+unsafe impl Sync for Enc28j60Adapter {}
+
+impl Enc28j60Adapter {
+    fn try_new(spidev: spi::Device, config: BoardConfig) -> Result<Arc<Self>> {
+        // SAFETY: `spidev` is a bound SPI device.
+        let max_transfer = unsafe { bindings::spi_max_transfer_size(raw_spi(&spidev)) };
+        let rx_chunk_max = max_transfer.saturating_sub(1).max(1);
+        let rx_single_read_max = rx_chunk_max
+            .saturating_sub(RxStatusVector::size())
+            .min(RX_SINGLE_READ_DEFAULT as _) as u16;
+
+        // Oscillator start-up after power-on is covered by the CLKRDY wait
+        // in `init_hardware`
+        let mut vdd = Regulator::get_optional(&spidev, c_str!("vdd"))?;
+        if let Some(vdd) = &mut vdd {
+            vdd.enable()?;
+        }
+
+        let tx_queue_limit = match *tx_queue_len.read() as usize {
+            len @ 1..=TX_QUEUE_MAX => len,
+            len => {
+                let clamped = len.clamp(1, TX_QUEUE_MAX);
+                dev_warn!(
+                    from_dev(&spidev),
+                    "tx_queue_len {} out of range, using {}\n",
+                    len,
+                    clamped
+                );
+                clamped
+            }
+        };
+        let rx_reserve = config.rx_reserve;
+        let force_full_duplex = config.full_duplex;
+        let rx_filter_base = RxFilter::from_param(&spidev);
+        // The module parameter forces polling even with an IRQ
+        let poll_us = match *poll_interval_us.read() {
+            0 => config.poll_interval_us,
+            us => us,
+        };
+        let mut driver = Enc28j60Driver {
+            bank: Bank::Bank0,
+            spidev,
+            config,
+            netdev_reg: None,
+            irq: None,
+            debugfs: None,
+            debug_reg: None,
+            next_packet_ptr: 0,
+            rx_single_read_max,
+            rx_chunk_max,
+            rx_paused: false,
+            rx_reserve,
+            rx_pause: true,
+            tx_pause: true,
+            rx_frozen: false,
+            rx_coalesce_usecs: 0,
+            rx_coalesce_frames: 0,
+            rx_deferred: false,
+            phy_scan: None,
+            link_up: false,
+            full_duplex: true,
+            force_full_duplex,
+            notify_peers: false,
+            link_changed: None,
+            mtu: bindings::ETH_DATA_LEN,
+            max_frame_len: ETH_MAX_FRAME_LEN,
+            wol: 0,
+            wol_armed: false,
+            suspended: false,
+            tx_in_flight: false,
+            tx_retries: 0,
+            tx_fifo_ready: false,
+            rx_filter: rx_filter_base,
+            rx_fifo: RXFIFO_INIT,
+            tx_fifo: TXFIFO_INIT,
+            rx_broadcast: 0,
+            rx_pause_frames: 0,
+            rx_own_src: 0,
+            rx_err_logged: None,
+            rx_err_suppressed: 0,
+            msg_enable: MSG_ENABLE_DEFAULT,
+            bank_switches: 0,
+            errata: 0,
+            phy_jabbers: 0,
+            rx_pool: Vec::try_with_capacity(RX_POOL_SIZE)?,
+            xfer_buf: [0; VLAN_MAX_FRAME_LEN as usize + 4],
+            vdd,
+        };
+
+        // A chip that doesn't answer or a bad configuration won't get any
+        // better, but a failed SPI transfer may be the controller or its
+        // supply not being ready yet, so probe is retried later for those.
+        match driver.init_hardware() {
+            Err(e) if e != ENODEV && e != EINVAL && e != ENOMEM => {
+                dev_dbg!(
+                    from_dev(&driver.spidev),
+                    "hardware init failed ({:?}), deferring probe\n",
+                    e
+                );
+                return Err(Error::from_kernel_errno(-(bindings::EPROBE_DEFER as i32)));
+            }
+            result => result?,
+        }
+        let revision = driver.read(EREVID)?;
+        dev_info!(
+            from_dev(&driver.spidev),
+            "silicon rev {} ({:#04x}), errata workarounds: ERXRDPT odd {}, TX logic reset {}\n",
+            Revision::name(revision),
+            revision,
+            driver.errata & errata::ERXRDPT_ODD != 0,
+            driver.errata & errata::TX_LOGIC_RESET != 0
+        );
+        let bus_info = CString::try_from_fmt(fmt!("{}", driver.spidev.name()))?;
+
+        let mut adapter = UniqueArc::try_new(Enc28j60Adapter {
+            driver: Mutex::new(driver),
+            revision,
+            bus_info,
+            stats: Stats::default(),
+            workqueue: workqueue::Queue::try_new(fmt!("enc28j60_wq"))?,
+            // SAFETY: Initialized immediately in the following statements.
+            irq_work: unsafe { workqueue::Work::new() },
+            tx_work: unsafe { workqueue::Work::new() },
+            tx_queue: unsafe { SpinLock::new(TxQueue::new(tx_queue_limit)) },
+            rx_mode_work: unsafe { workqueue::Work::new() },
+            tx_timeout_work: unsafe { workqueue::Work::new() },
+            rx_mode: unsafe { SpinLock::new(rx_filter_base) },
+            rx_filter_base,
+            poll_timer: Opaque::uninit(),
+            poll_interval: poll_us as i64 * 1000,
+            poll_ref: AtomicPtr::new(ptr::null_mut()),
+            poll_worker: AtomicPtr::new(ptr::null_mut()),
+            poll_work: Opaque::uninit(),
+            poll_work_ref: AtomicPtr::new(ptr::null_mut()),
+            coalesce_timer: Opaque::uninit(),
+            coalesce_ref: AtomicPtr::new(ptr::null_mut()),
+            coalesce_expired: AtomicBool::new(false),
+            rev_attr: Opaque::uninit(),
+        })?;
+        kernel::init_work_item_adapter!(IrqWorkHandler, &adapter);
+        kernel::init_work_item_adapter!(TxWorkHandler, &adapter);
+        kernel::init_work_item_adapter!(RxModeWorkHandler, &adapter);
+        kernel::init_work_item_adapter!(TxTimeoutWorkHandler, &adapter);
+        kernel::spinlock_init!(
+            unsafe { Pin::new_unchecked(&mut adapter.tx_queue) },
+            "enc_skb"
+        );
+        kernel::spinlock_init!(
+            unsafe { Pin::new_unchecked(&mut adapter.rx_mode) },
+            "enc_rx_mode"
+        );
+        // SAFETY: The timers are initialized before the adapter is shared.
+        unsafe {
+            let timer = adapter.poll_timer.get();
+            bindings::hrtimer_init(
+                timer,
+                bindings::CLOCK_MONOTONIC as _,
+                bindings::hrtimer_mode_HRTIMER_MODE_REL,
+            );
+            (*timer).function = Some(poll_timer_fn);
+
+            let timer = adapter.coalesce_timer.get();
+            bindings::hrtimer_init(
+                timer,
+                bindings::CLOCK_MONOTONIC as _,
+                bindings::hrtimer_mode_HRTIMER_MODE_REL,
+            );
+            (*timer).function = Some(coalesce_timer_fn);
+
+            // Open-coded `kthread_init_work`, which is a macro
+            let work = adapter.poll_work.get();
+            ptr::write_bytes(work, 0, 1);
+            (*work).node.next = ptr::addr_of_mut!((*work).node);
+            (*work).node.prev = ptr::addr_of_mut!((*work).node);
+            (*work).func = Some(poll_work_fn);
+        }
+        if adapter.poll_interval != 0 {
+            *adapter.poll_worker.get_mut() = Self::create_poll_worker()?;
+        }
+
+        Ok(adapter.into())
+    }
+
+    // A SCHED_FIFO kthread services the chip when polling, so that the
+    // latency from a poll to the servicing doesn't depend on when a
+    // workqueue thread gets to run. That's what polling is for over the IRQ,
+    // whose threaded handling has the same jitter.
+    fn create_poll_worker() -> Result<*mut bindings::kthread_worker> {
+        // SAFETY: The name is a C string without conversion specifiers.
+        let worker =
+            unsafe { bindings::kthread_create_worker(0, c_str!("enc28j60_poll").as_char_ptr()) };
+        let errno = worker as isize;
+        if (-(bindings::MAX_ERRNO as isize)..0).contains(&errno) {
+            return Err(Error::from_kernel_errno(errno as i32));
+        }
+
+        // SAFETY: `worker` was just created and its task is running.
+        unsafe { bindings::sched_set_fifo((*worker).task) };
+        Ok(worker)
+    }
+
+    // Runs the queued work, if any, which releases its `Arc` reference
+    fn destroy_poll_worker(&self) {
+        let worker = self.poll_worker.swap(ptr::null_mut(), Ordering::AcqRel);
+        if !worker.is_null() {
+            // SAFETY: `worker` came from `create_poll_worker` and the poll
+            // timer, which queues work on it, is cancelled.
+            unsafe { bindings::kthread_destroy_worker(worker) };
+        }
+    }
+
+    fn request_irq(self: &Arc<Self>) -> Result {
+        let mut driver = self.driver.lock();
+
+        let irq = driver.spidev.get_irq() as _;
+
+        // INT is an active-low level output which stays asserted until every
+        // pending EIR flag is cleared, so an edge trigger can miss events.
+        // ONESHOT keeps the line masked until `handle_threaded_irq` has
+        // cleared INTIE, so it doesn't keep firing until the work runs.
+        // SAFETY: `irq` is the interrupt line assigned to our SPI device.
+        let trigger = unsafe { bindings::irq_get_trigger_type(irq) };
+        if trigger & bindings::IRQ_TYPE_EDGE_BOTH != 0 {
+            dev_warn!(
+                from_dev(&driver.spidev),
+                "IRQ {} is configured edge-triggered ({:#x}), forcing level-low\n",
+                irq,
+                trigger
+            );
+        }
+
+        driver.irq = Some(irq::ThreadedRegistration::try_new(
+            irq,
+            self.clone(),
+            irq::flags::SHARED | irq::flags::TRIGGER_LOW | irq::flags::ONESHOT,
+            fmt!("enc28j60_{irq}"),
+        )?);
+        // SAFETY: `raw_device` is valid as long as `spidev` is alive.
+        unsafe { bindings::device_set_wakeup_capable(driver.spidev.raw_device(), true) };
+
+        Ok(())
+    }
+
+    fn start_poll_timer(self: &Arc<Self>) {
+        if self.poll_interval == 0 {
+            return;
+        }
+
+        self.start_timer(&self.poll_timer, &self.poll_ref, self.poll_interval);
+    }
+
+    // Holds off RX interrupts after a batch, see `rx_coalesce_usecs`
+    fn start_coalesce_timer(self: &Arc<Self>, usecs: u32) {
+        self.coalesce_expired.store(false, Ordering::Release);
+        self.start_timer(
+            &self.coalesce_timer,
+            &self.coalesce_ref,
+            usecs as i64 * 1000,
+        );
+    }
+
+    // `timer_ref` keeps the adapter alive for the callback until `stop_timer`
+    fn start_timer(
+        self: &Arc<Self>,
+        timer: &Opaque<bindings::hrtimer>,
+        timer_ref: &AtomicPtr<c_void>,
+        interval: i64,
+    ) {
+        let old = timer_ref.swap(self.clone().into_foreign() as _, Ordering::AcqRel);
+        if !old.is_null() {
+            // SAFETY: `old` came from `into_foreign` of a previous start.
+            drop(unsafe { Arc::<Self>::from_foreign(old) });
+        }
+
+        // SAFETY: The timer was initialized in `try_new`.
+        unsafe {
+            bindings::hrtimer_start_range_ns(
+                timer.get(),
+                interval,
+                0,
+                bindings::hrtimer_mode_HRTIMER_MODE_REL,
+            )
+        };
+    }
+
+    fn stop_timers(&self) {
+        Self::stop_timer(&self.poll_timer, &self.poll_ref);
+        Self::stop_timer(&self.coalesce_timer, &self.coalesce_ref);
+    }
+
+    fn stop_timer(timer: &Opaque<bindings::hrtimer>, timer_ref: &AtomicPtr<c_void>) {
+        // SAFETY: The timer was initialized in `try_new`. Cancelling waits for
+        // a running callback, so `timer_ref` is no longer used afterwards.
+        unsafe { bindings::hrtimer_cancel(timer.get()) };
+
+        let old = timer_ref.swap(ptr::null_mut(), Ordering::AcqRel);
+        if !old.is_null() {
+            // SAFETY: `old` came from `into_foreign` in `start_timer`.
+            drop(unsafe { Arc::<Self>::from_foreign(old) });
+        }
+    }
+
+    fn device_suspend(self: &Arc<Self>) -> Result {
+        let mut driver = self.driver.lock();
+        // Under the lock, so that the IRQ work can't rearm them afterwards
+        self.stop_timers();
+
+        let netdev = driver.netdev();
+        // SAFETY: The netdev is registered until `device_remove`.
+        if !unsafe { bindings::netif_running(raw_netdev(&netdev)) } {
+            // `open` reinitializes the chip from scratch anyway
+            driver.power_off();
+            return Ok(());
+        }
+        // SAFETY: As above.
+        unsafe { bindings::netif_device_detach(raw_netdev(&netdev)) };
+        driver.suspended = true;
+
+        if let Err(e) = driver.prepare_suspend() {
+            dev_err!(from_dev(&driver.spidev), "Failed to suspend: {:?}\n", e);
+            // The chip is brought up from scratch again, which also restores
+            // the EIE and ERXFCON settings changed for WoL
+            self.leave_suspend(driver);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    // The chip may have lost power, so it's brought up from scratch as in `open`
+    fn device_resume(self: &Arc<Self>) -> Result {
+        self.leave_suspend(self.driver.lock());
+        Ok(())
+    }
+
+    // Undoes `device_suspend`, also one that failed halfway. A failure to
+    // bring the chip back up is only logged: the netdev is attached with the
+    // carrier off regardless, so that it isn't left detached for good and a
+    // later `open` can retry.
+    fn leave_suspend(self: &Arc<Self>, mut driver: Guard<'_, Enc28j60Driver>) {
+        if core::mem::take(&mut driver.wol_armed) {
+            // SAFETY: The IRQ was armed as a wake source in `device_suspend`.
+            unsafe { bindings::disable_irq_wake(driver.spidev.get_irq() as _) };
+        }
+        let detached = core::mem::take(&mut driver.suspended);
+
+        let netdev = driver.netdev();
+        if let Err(e) = self.power_on(&netdev, &mut driver) {
+            dev_err!(from_dev(&driver.spidev), "Failed to resume: {:?}\n", e);
+            driver.link_up = false;
+            netdev.netif_carrier_off();
+        }
+        drop(driver);
+
+        if detached {
+            // SAFETY: The netdev is registered until `device_remove`.
+            unsafe { bindings::netif_device_attach(raw_netdev(&netdev)) };
+            self.start_poll_timer();
+        }
+    }
+
+    fn power_on(&self, netdev: &net::Device, driver: &mut Enc28j60Driver) -> Result {
+        // Still on if the chip was armed for WoL or the suspend failed early
+        if let Some(vdd) = &mut driver.vdd {
+            vdd.enable()?;
+        }
+
+        // SAFETY: The netdev is registered until `device_remove`.
+        if !unsafe { bindings::netif_running(raw_netdev(netdev)) } {
+            return Ok(());
+        }
+        // Harmless if the chip stayed powered for WoL or lost power anyway
+        driver.leave_power_save()?;
+        // Also restarts TX, the reset aborted any frame in flight
+        self.reconfigure_running(netdev, driver)
+    }
+
+    // Hands the head of `tx_queue` to the chip unless a frame is in flight
+    // already. Called when a frame is queued and whenever one completes.
+    // A frame that can't be sent is dropped and the next one tried, so a bad
+    // skb never ends the caller's work early.
+    fn start_queued_tx(&self, driver: &mut Enc28j60Driver) {
+        while !driver.tx_in_flight {
+            // A TX error or the device removal may have dropped the frame
+            // that `TxWorkHandler` was queued for
+            let skb = match self.tx_queue.lock().front() {
+                Some(skb) => skb,
+                None => return,
+            };
+            let skb_data = skb.head_data();
+
+            // A failed (re)initialization may have left the TX FIFO
+            // unconfigured, and an oversized frame would put ETXND past the
+            // end of the TX FIFO. A frame `start_xmit` couldn't linearize
+            // would go out truncated.
+            let oversized = skb_data.len() > Enc28j60Driver::tx_capacity(&driver.tx_fifo) as usize;
+            // SAFETY: `skb` is a valid `sk_buff`.
+            let nonlinear = unsafe { (*raw_skb(&skb)).data_len } != 0;
+            let result = if oversized {
+                Err(EMSGSIZE)
+            } else if nonlinear {
+                Err(ENOMEM)
+            } else if !driver.tx_fifo_ready {
+                Err(EIO)
+            } else {
+                driver.tx_retries = 0;
+                driver.start_tx(skb_data, skb_csum_partial(&skb))
+            };
+
+            match result {
+                // SAFETY: `skb` is a valid `sk_buff` that has been handed to the chip.
+                Ok(()) => unsafe { bindings::skb_tx_timestamp(raw_skb(&skb)) },
+                Err(e) => {
+                    netif_msg!(
+                        driver,
+                        NETIF_MSG_TX_ERR_BIT,
+                        dev_err,
+                        "TX frame dropped: {}\n",
+                        e.to_kernel_errno()
+                    );
+                    drop(skb);
+                    let _ = self.tx_queue.lock().pop();
+                    driver.update_stats(|stats| stats.tx_dropped += 1);
+                    driver.netdev().netif_wake_queue();
+                }
+            }
+        }
+    }
+
+    // Applies settings that need a chip reset to a running interface. They
+    // are otherwise applied by `open`.
+    fn reconfigure_running(&self, dev: &net::Device, driver: &mut Enc28j60Driver) -> Result {
+        // SAFETY: `dev` is the registered netdev of this adapter.
+        if !unsafe { bindings::netif_running(raw_netdev(dev)) } {
+            return Ok(());
+        }
+
+        let tx_in_flight = driver.tx_in_flight;
+        driver.reset_and_reconfigure(dev)?;
+        driver.check_link_status()?;
+
+        // The reset aborted the frame in flight, if any, the next one can go
+        if tx_in_flight && self.tx_queue.lock().pop().is_some() {
+            dev.netif_wake_queue();
+        }
+
+        self.start_queued_tx(driver);
+        Ok(())
+    }
+
+    fn register_netdev(self: &Arc<Self>) -> Result {
+        let mut driver = self.driver.lock();
+        let mut netdev_reg = net::Registration::try_new(&driver.spidev)?;
+
+        let netdev = netdev_reg.dev_get();
+        // A provisioned address takes precedence over generated ones
+        if !driver.set_dt_macaddr(&netdev)? {
+            if driver.config.stable_macaddr {
+                driver.set_stable_macaddr(&netdev)?;
+            } else {
+                driver.set_random_macaddr(&netdev)?;
+            }
+        }
+        netdev.set_if_port(bindings::IF_PORT_10BASET as _);
+        netdev.set_irq(driver.spidev.get_irq());
+        netdev.set_ethtool_ops::<Self>();
+        // TX checksum offload is on by default and can be toggled with
+        // `ethtool -K`. No `ndo_set_features` is needed for that as
+        // `start_queued_tx` only fills in checksums the stack asks for per skb.
+        // Likewise RX FCS passthrough, off by default, is checked per frame
+        // in `handle_rx_packet`.
+        // SAFETY: `netdev` isn't registered yet, so nothing else accesses it.
+        unsafe {
+            let raw = raw_netdev(&netdev);
+            (*raw).watchdog_timeo = bindings::__msecs_to_jiffies(TX_TIMEOUT_MS) as _;
+            (*raw).min_mtu = bindings::ETH_MIN_MTU;
+            (*raw).max_mtu =
+                (ETH_MAX_FRAME_LEN as u32) - bindings::ETH_HLEN - bindings::ETH_FCS_LEN;
+            (*raw).hw_features |= 1 << bindings::NETIF_F_HW_CSUM_BIT;
+            (*raw).hw_features |= 1 << bindings::NETIF_F_RXFCS_BIT;
+            (*raw).features |= 1 << bindings::NETIF_F_HW_CSUM_BIT;
+        }
+
+        netdev_reg.register(self.clone())?;
+        driver.netdev_reg = Some(netdev_reg);
+
+        // Failing probe now would leak the registered netdev, and the file
+        // is only informational
+        if let Err(e) = self.create_rev_attr(&netdev) {
+            dev_warn!(
+                from_dev(&driver.spidev),
+                "failed to create silicon_rev: {}\n",
+                e.to_kernel_errno()
+            );
+        }
+
+        Ok(())
+    }
+
+    // EREVID as read at probe, e.g. "B7 (0x06)", to tell the revisions
+    // of a fleet apart
+    fn create_rev_attr(&self, netdev: &net::Device) -> Result {
+        let ext = self.rev_attr.get();
+        // SAFETY: `rev_attr` isn't used by sysfs until `device_create_file`,
+        // and both it and `revision` live as long as the adapter, which
+        // removes the file in `device_remove`.
+        let ret = unsafe {
+            ext.write(core::mem::zeroed());
+            (*ext).attr.attr.name = c_str!("silicon_rev").as_char_ptr();
+            (*ext).attr.attr.mode = 0o444;
+            // Not a static object, so it has no lockdep key of its own
+            #[cfg(CONFIG_DEBUG_LOCK_ALLOC)]
+            (*ext).attr.attr.set_ignore_lockdep(true);
+            (*ext).attr.show = Some(silicon_rev_show);
+            (*ext).var = &self.revision as *const u8 as *mut c_void;
+            bindings::device_create_file(&mut (*raw_netdev(netdev)).dev, &(*ext).attr)
+        };
+        if ret < 0 {
+            return Err(Error::from_kernel_errno(ret));
+        }
+
+        Ok(())
+    }
+
+    // Best-effort like debugfs itself: it runs after the netdev and IRQ are
+    // registered, which failing probe would leak, so errors are ignored
+    fn register_debugfs(self: &Arc<Self>) {
+        let mut driver = self.driver.lock();
+
+        let name = match CString::try_from_fmt(fmt!("enc28j60-{}", driver.spidev.name())) {
+            Ok(name) => name,
+            Err(_) => return,
+        };
+        let mut dir = enc28j60_debugfs::Dir::new(&name);
+        let _ = dir.create_file::<LinkFile>(self);
+        let _ = dir.create_file::<StateFile>(self);
+        let _ = dir.create_file::<FifoFile>(self);
+        let _ = dir.create_file::<RxSingleReadFile>(self);
+        if cfg!(debug_assertions) {
+            let _ = dir.create_file::<RegFile>(self);
+            let _ = dir.create_file::<RxFreezeFile>(self);
+        }
+
+        driver.debugfs = Some(dir);
+    }
+}
+
+impl driver::DeviceRemoval for Enc28j60Adapter {
+    fn device_remove(&self) {
+        drop({
+            let mut driver = self.driver.lock();
+            driver.debugfs.take()
+        });
+
+        drop({
+            let mut driver = self.driver.lock();
+            driver.irq.take()
+        });
+
+        self.workqueue.flush();
+
+        drop({
+            let mut driver = self.driver.lock();
+            if let Some(netdev_reg) = &driver.netdev_reg {
+                // SAFETY: The netdev is still registered. Removing a file
+                // that `create_rev_attr` failed to create is harmless.
+                unsafe {
+                    bindings::device_remove_file(
+                        &mut (*raw_netdev(&netdev_reg.dev_get())).dev,
+                        &(*self.rev_attr.get()).attr,
+                    )
+                };
+            }
+            driver.netdev_reg.take()
+        });
+
+        // Unregistering has run `stop` if the interface was up, and the IRQ
+        // work is a no-op without `netdev_reg`, so nothing arms them again
+        self.stop_timers();
+        // Not left to `drop`, which the last run on the worker could call
+        self.destroy_poll_worker();
+
+        // Unregistering stopped the queue, nothing adds to it any more
+        self.tx_queue.lock().clear();
+
+        // Unregistering has stopped the interface, nothing talks to the chip
+        // any more
+        if let Some(vdd) = &mut self.driver.lock().vdd {
+            let _ = vdd.disable();
+        }
+    }
+}
+
+// Probe may fail after `try_new`, in which case `device_remove` doesn't run
+impl Drop for Enc28j60Adapter {
+    fn drop(&mut self) {
+        self.destroy_poll_worker();
+    }
+}
+
+#[vtable]
+impl net::DeviceOperations for Enc28j60Adapter {
+    type Data = Arc<Enc28j60Adapter>;
+
+    fn open(dev: &net::Device, adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Result {
+        let mut driver = adapter.driver.lock();
+
+        driver.reset_and_reconfigure(dev)?;
+        if *reset_stats_on_open.read() {
+            driver.reset_stats(dev);
+            adapter.stats.reset();
+        }
+        driver.check_link_status()?;
+        driver.refill_rx_pool();
+        // The stack announces the interface itself when it comes up
+        driver.notify_peers = false;
+
+        dev.netif_start_queue();
+
+        Arc::from(adapter).start_poll_timer();
+
+        Ok(())
+    }
+
+    // Don't use `netdev_reg` as it might be None
+    fn stop(dev: &net::Device, adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Result {
+        let mut driver = adapter.driver.lock();
+        // `dev` isn't running any more, so the IRQ work won't rearm them once
+        // it gets the lock
+        adapter.stop_timers();
+
+        dev.netif_stop_queue();
+        // With the interrupts off these never complete, `open` starts afresh
+        adapter.tx_queue.lock().clear();
+
+        driver.disable_hardware()?;
+
+        driver.link_up = false;
+        driver.link_changed = None;
+        driver.rx_pool.clear();
+
+        Ok(())
+    }
+
+    fn set_rx_mode(dev: &net::Device, adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>) {
+        *adapter.rx_mode.lock_irqdisable() = RxFilter::from_netdev(dev, adapter.rx_filter_base);
+
+        adapter
+            .workqueue
+            .enqueue_adapter::<RxModeWorkHandler>(adapter.into());
+    }
+
+    // Called by the core before `open`
+    fn validate_addr(
+        dev: &net::Device,
+        _adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+    ) -> Result {
+        if !is_valid_ether_addr(&dev.device_address()[..bindings::ETH_ALEN as usize]) {
+            return Err(EADDRNOTAVAIL);
+        }
+        Ok(())
+    }
+
+    // MAADR must not change while receiving, so RX is stopped around the write
+    fn set_mac_address(
+        dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        addr: &bindings::sockaddr,
+    ) -> Result {
+        let mut new_addr = [0u8; bindings::ETH_ALEN as usize];
+        for (dst, src) in new_addr.iter_mut().zip(addr.sa_data.iter()) {
+            *dst = *src as _;
+        }
+        if !is_valid_ether_addr(&new_addr) {
+            return Err(EADDRNOTAVAIL);
+        }
+
+        let mut driver = adapter.driver.lock();
+        let rxen = driver.read(ECON1)? & econ1::RXEN;
+        driver.write(ECON1, Command::Bfc, econ1::RXEN)?;
+
+        // `dev_addr` only follows once MAADR holds the new address, a partly
+        // written one is reverted
+        let result = driver.write_maadr(&new_addr);
+        match result {
+            Ok(()) => dev.eth_hw_addr_set(&new_addr),
+            Err(_) => {
+                let _ = driver.set_hw_macaddr(dev);
+            }
+        }
+
+        // Attempted whether or not the address changed, RX must not stay off
+        let restored = driver.write(ECON1, Command::Bfs, rxen);
+        if let Err(e) = restored {
+            dev_err!(
+                from_dev(&driver.spidev),
+                "Failed to re-enable RX: {:?}\n",
+                e
+            );
+        }
+        result.and(restored)
+    }
+
+    // The range is checked against `min_mtu`/`max_mtu` by the core already
+    fn change_mtu(
+        dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        new_mtu: i32,
+    ) -> Result {
+        adapter.driver.lock().apply_mtu(new_mtu as u32)?;
+        // SAFETY: `dev` is the registered netdev the callback is called for,
+        // and the core holds the RTNL lock that serializes MTU changes.
+        unsafe { (*raw_netdev(dev)).mtu = new_mtu as _ };
+
+        Ok(())
+    }
+
+    fn tx_timeout(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _txqueue: u32,
+    ) {
+        adapter
+            .workqueue
+            .enqueue_adapter::<TxTimeoutWorkHandler>(adapter.into());
+    }
+
+    // The error counters are kept in `net_device_stats`
+    fn get_stats64(
+        dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        storage: &mut bindings::rtnl_link_stats64,
+    ) {
+        // SAFETY: `dev` is the registered netdev the callback is called for.
+        unsafe { bindings::netdev_stats_to_stats64(storage, &(*raw_netdev(dev)).stats) };
+
+        let stats = &adapter.stats;
+        storage.rx_packets = stats.rx_packets.load(Ordering::Relaxed);
+        storage.rx_bytes = stats.rx_bytes.load(Ordering::Relaxed);
+        storage.tx_packets = stats.tx_packets.load(Ordering::Relaxed);
+        storage.tx_bytes = stats.tx_bytes.load(Ordering::Relaxed);
+        storage.tx_dropped += stats.tx_dropped.load(Ordering::Relaxed);
+    }
+
+    // PHY access for mii-tool and the like. Reads are limited to the registers
+    // of the `ethtool -d` dump, writes to those boards may override.
+    fn eth_ioctl(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        ifr: &mut bindings::ifreq,
+        cmd: i32,
+    ) -> Result {
+        // SAFETY: The MII ioctls carry `mii_ioctl_data` in `ifr_ifru`, which is
+        // what `if_mii` returns.
+        let mii = unsafe { &mut *(&mut ifr.ifr_ifru as *mut _ as *mut bindings::mii_ioctl_data) };
+        let find = |regs: &[PhyRegister]| {
+            regs.iter()
+                .copied()
+                .find(|reg| reg.addr as u16 == mii.reg_num)
+                .ok_or(EINVAL)
+        };
+
+        match cmd as u32 {
+            bindings::SIOCGMIIPHY => {
+                // The only PHY is the internal one
+                mii.phy_id = 0;
+                Ok(())
+            }
+            bindings::SIOCGMIIREG | bindings::SIOCSMIIREG if mii.phy_id != 0 => Err(EINVAL),
+            bindings::SIOCGMIIREG => {
+                let reg = find(&REGS_DUMP_PHY)?;
+                mii.val_out = adapter.driver.lock().read_phy(reg)?;
+                Ok(())
+            }
+            bindings::SIOCSMIIREG => {
+                let reg = find(&OVERRIDABLE_PHY)?;
+                adapter.driver.lock().write_phy(reg, mii.val_in)
+            }
+            _ => Err(EOPNOTSUPP),
+        }
+    }
+
+    fn start_xmit(
+        skb: &net::SkBuff,
+        dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+    ) -> net::NetdevTx {
+        // Without a link the frame would sit in the TX FIFO until it's
+        // dropped by the TX timeout. Returning without keeping a reference
+        // frees it.
+        // SAFETY: `dev` is the registered netdev the callback is called for.
+        if !unsafe { bindings::netif_carrier_ok(raw_netdev(dev)) } {
+            adapter.stats.tx_dropped.fetch_add(1, Ordering::Relaxed);
+            return net::NetdevTx::Ok;
+        }
+
+        // Only `start_queued_tx` sends `head_data`. Without NETIF_F_SG the
+        // core linearizes frames already, but nothing else guarantees it, and
+        // it can't be done later once `tx_queue` shares the skb. On failure
+        // the frame is dropped by `start_queued_tx`.
+        // SAFETY: `skb` is a valid `sk_buff` owned by us and not shared yet.
+        let _ = unsafe { bindings::skb_linearize(raw_skb(skb)) };
+
+        // The chip sends one frame at a time, `tx_queue` keeps the next ones
+        // so that they can be written to the TX FIFO as soon as it's free.
+        // The queue stays stopped while `tx_queue` holds `tx_queue_len`
+        // frames, the one in flight included, so the qdisc sees the SPI drain
+        // rate. A frame completing (TXIF/TXERIF) or being dropped
+        // (`start_queued_tx`) wakes it again.
+        let mut queue = adapter.tx_queue.lock_irqdisable();
+        let rejected = queue.push(skb.into()).err();
+        let idle = queue.len() == 1 && rejected.is_none();
+        if queue.is_full() {
+            dev.netif_stop_queue();
+        }
+        drop(queue);
+
+        // Not expected as the queue is stopped while `tx_queue` is full
+        if let Some(skb) = rejected {
+            drop(skb);
+            adapter.stats.tx_dropped.fetch_add(1, Ordering::Relaxed);
+            return net::NetdevTx::Ok;
+        }
+
+        // Otherwise the completion of the frame ahead starts this one
+        if idle {
+            adapter
+                .workqueue
+                .enqueue_adapter::<TxWorkHandler>(adapter.into());
+        }
+
+        net::NetdevTx::Ok
+    }
+}
+
+#[vtable]
+impl net::EthtoolOps for Enc28j60Adapter {
+    type Data = Arc<Enc28j60Adapter>;
+
+    const SUPPORTED_COALESCE_PARAMS: u32 =
+        bindings::ETHTOOL_COALESCE_RX_USECS | bindings::ETHTOOL_COALESCE_RX_MAX_FRAMES;
+
+    fn get_drvinfo(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        info: &mut bindings::ethtool_drvinfo,
+    ) {
+        copy_to_cstr(&mut info.driver, b"enc28j60rs");
+        copy_to_cstr(&mut info.bus_info, adapter.bus_info.as_bytes());
+        if let Ok(revision) = CString::try_from_fmt(fmt!("rev {:#04x}", adapter.revision)) {
+            copy_to_cstr(&mut info.fw_version, revision.as_bytes());
+        }
+    }
+
+    fn get_msglevel(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+    ) -> u32 {
+        adapter.driver.lock().msg_enable
+    }
+
+    fn set_msglevel(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        value: u32,
+    ) {
+        adapter.driver.lock().msg_enable = value;
+    }
+
+    // The state `check_link_status` last saw, down until the first check
+    fn get_link(_dev: &net::Device, adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> u32 {
+        adapter.driver.lock().link_up as _
+    }
+
+    // There is no autonegotiation to restart, so `ethtool -r` resets the PHY
+    // to retrain a stuck link without bouncing the interface
+    fn nway_reset(
+        dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+    ) -> Result {
+        // SAFETY: `dev` is the registered netdev the callback is called for.
+        let running = unsafe { bindings::netif_running(raw_netdev(dev)) };
+        adapter.driver.lock().reset_phy(running)
+    }
+
+    // The PHY is 10BASE-T only and has no autonegotiation, the duplex is
+    // always forced through PHCON1.PDPXMD
+    fn get_link_ksettings(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        cmd: &mut bindings::ethtool_link_ksettings,
+    ) -> Result {
+        let full_duplex = adapter.driver.lock().full_duplex;
+
+        for mask in [
+            &mut cmd.link_modes.supported[..],
+            &mut cmd.link_modes.advertising[..],
+        ] {
+            for bit in [
+                bindings::ethtool_link_mode_bit_indices_ETHTOOL_LINK_MODE_10baseT_Half_BIT,
+                bindings::ethtool_link_mode_bit_indices_ETHTOOL_LINK_MODE_10baseT_Full_BIT,
+                bindings::ethtool_link_mode_bit_indices_ETHTOOL_LINK_MODE_TP_BIT,
+            ] {
+                let bits = core::mem::size_of_val(&mask[0]) * 8;
+                mask[bit as usize / bits] |= 1 << (bit as usize % bits);
+            }
+        }
+
+        cmd.base.speed = bindings::SPEED_10;
+        cmd.base.duplex = if full_duplex {
+            bindings::DUPLEX_FULL
+        } else {
+            bindings::DUPLEX_HALF
+        } as _;
+        cmd.base.port = bindings::PORT_TP as _;
+        cmd.base.autoneg = bindings::AUTONEG_DISABLE as _;
+
+        Ok(())
+    }
+
+    // Only the duplex can be chosen. The chip is reset to apply it as the MAC
+    // must not be reconfigured while enabled.
+    fn set_link_ksettings(
+        dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        cmd: &bindings::ethtool_link_ksettings,
+    ) -> Result {
+        if cmd.base.speed != bindings::SPEED_10
+            || cmd.base.autoneg != bindings::AUTONEG_DISABLE as u8
+        {
+            return Err(EINVAL);
+        }
+        let full_duplex = match cmd.base.duplex as u32 {
+            bindings::DUPLEX_FULL => true,
+            bindings::DUPLEX_HALF => false,
+            _ => return Err(EINVAL),
+        };
+
+        let mut driver = adapter.driver.lock();
+        if full_duplex == driver.force_full_duplex {
+            return Ok(());
+        }
+        driver.force_full_duplex = full_duplex;
+
+        adapter.reconfigure_running(dev, &mut driver)
+    }
+
+    // The chip has a single RX and a single TX FIFO, so multiple queues
+    // are not possible
+    fn get_channels(
+        _dev: &net::Device,
+        _adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        channels: &mut bindings::ethtool_channels,
+    ) {
+        channels.max_combined = 1;
+        channels.combined_count = 1;
+    }
+
+    fn set_channels(
+        _dev: &net::Device,
+        _adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        channels: &bindings::ethtool_channels,
+    ) -> Result {
+        match (
+            channels.combined_count,
+            channels.rx_count,
+            channels.tx_count,
+            channels.other_count,
+        ) {
+            (1, 0, 0, 0) => Ok(()),
+            _ => Err(EINVAL),
+        }
+    }
+
+    // Ring sizes are the bytes of the 8 KB buffer given to the RX and TX FIFOs
+    fn get_ringparam(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        ring: &mut bindings::ethtool_ringparam,
+    ) {
+        let driver = adapter.driver.lock();
+        let size = |range: &FifoRange| (range.end() - range.start() + 1) as u32;
+
+        ring.rx_max_pending = BUFFER_SIZE as u32 - 1;
+        ring.tx_max_pending = BUFFER_SIZE as u32 - 1;
+        ring.rx_pending = size(&driver.rx_fifo);
+        ring.tx_pending = size(&driver.tx_fifo);
+    }
+
+    // RX stays at the start of the buffer as the errata require ERXST to be
+    // 0, TX follows it
+    fn set_ringparam(
+        dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        ring: &bindings::ethtool_ringparam,
+    ) -> Result {
+        if ring.rx_mini_pending != 0 || ring.rx_jumbo_pending != 0 {
+            return Err(EINVAL);
+        }
+        if ring.rx_pending == 0
+            || ring.tx_pending == 0
+            || ring.rx_pending + ring.tx_pending > BUFFER_SIZE as u32
+        {
+            return Err(EINVAL);
+        }
+        let rx_fifo = 0..=(ring.rx_pending - 1) as u16;
+        let tx_fifo = ring.rx_pending as u16..=(ring.rx_pending + ring.tx_pending - 1) as u16;
+        Enc28j60Driver::validate_fifo_layout(&rx_fifo, &tx_fifo)?;
+
+        let mut driver = adapter.driver.lock();
+        // Both FIFOs must still hold a frame of the current MTU
+        let frame_len = driver.max_tagged_len() as u32;
+        if frame_len - bindings::ETH_FCS_LEN > Enc28j60Driver::tx_capacity(&tx_fifo) as u32
+            || frame_len + RxStatusVector::size() as u32 > ring.rx_pending
+            || driver.rx_reserve as u32 >= ring.rx_pending
+        {
+            return Err(EINVAL);
+        }
+        if rx_fifo == driver.rx_fifo && tx_fifo == driver.tx_fifo {
+            return Ok(());
+        }
+        driver.rx_fifo = rx_fifo;
+        driver.tx_fifo = tx_fifo;
+
+        // `init_hardware` moves the FIFOs and rewinds `next_packet_ptr`
+        adapter.reconfigure_running(dev, &mut driver)
+    }
+
+    // The chip can't coalesce by itself, so this delays turning PKTIE back
+    // on after the IRQ work received frames, see `rx_coalesce_usecs`
+    fn get_coalesce(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        coalesce: &mut bindings::ethtool_coalesce,
+    ) -> Result {
+        let driver = adapter.driver.lock();
+        coalesce.rx_coalesce_usecs = driver.rx_coalesce_usecs;
+        coalesce.rx_max_coalesced_frames = driver.rx_coalesce_frames;
+        Ok(())
+    }
+
+    fn set_coalesce(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        coalesce: &bindings::ethtool_coalesce,
+    ) -> Result {
+        // The poll timer services the chip regardless of PKTIE
+        if adapter.poll_interval != 0 {
+            return Err(EOPNOTSUPP);
+        }
+        // Only EPKTCNT tells the frame count and without a delay nothing
+        // would turn PKTIE back on
+        let frames = coalesce.rx_max_coalesced_frames;
+        if frames > u8::MAX as u32 || (frames > 1 && coalesce.rx_coalesce_usecs == 0) {
+            return Err(EINVAL);
+        }
+
+        let mut driver = adapter.driver.lock();
+        driver.rx_coalesce_usecs = coalesce.rx_coalesce_usecs;
+        driver.rx_coalesce_frames = frames;
+        Ok(())
+    }
+
+    // TX pause frames follow `update_flow_control` while enabled.
+    // There is no autonegotiation of pause on this PHY.
+    fn get_pauseparam(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        pause: &mut bindings::ethtool_pauseparam,
+    ) {
+        let driver = adapter.driver.lock();
+        pause.autoneg = 0;
+        pause.rx_pause = driver.rx_pause as _;
+        pause.tx_pause = driver.tx_pause as _;
+    }
+
+    fn set_pauseparam(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        pause: &bindings::ethtool_pauseparam,
+    ) -> Result {
+        if pause.autoneg != 0 {
+            return Err(EINVAL);
+        }
+
+        let mut driver = adapter.driver.lock();
+        let rx_pause = pause.rx_pause != 0;
+        if rx_pause {
+            driver.modify(MACON1, macon1::RXPAUS, 0)?;
+        } else {
+            driver.modify(MACON1, 0, macon1::RXPAUS)?;
+        }
+        driver.rx_pause = rx_pause;
+
+        // Releases a link partner paused at the moment before TXPAUS goes
+        let tx_pause = pause.tx_pause != 0;
+        driver.tx_pause = tx_pause;
+        driver.update_flow_control()?;
+        if tx_pause {
+            driver.modify(MACON1, macon1::TXPAUS, 0)?;
+        } else {
+            driver.modify(MACON1, 0, macon1::TXPAUS)?;
+        }
+
+        Ok(())
+    }
+
+    fn get_regs_len(
+        _dev: &net::Device,
+        _adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+    ) -> i32 {
+        REGS_DUMP_LEN as _
+    }
+
+    fn get_regs(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        regs: &mut bindings::ethtool_regs,
+        data: &mut [u8],
+    ) {
+        regs.version = REGS_DUMP_VERSION;
+
+        let mut driver = adapter.driver.lock();
+        let saved_bank = driver.bank;
+        let _ = move || -> Result {
+            let mut data = data.iter_mut();
+            for index in 0..4 {
+                let bank = Bank::from_index(index).ok_or(EINVAL)?;
+                for addr in 0..0x1b {
+                    let reg = ControlRegisterU8::raw(bank, addr).ok_or(EINVAL)?;
+                    *data.next().ok_or(EINVAL)? = driver.read(reg)?;
+                }
+            }
+            for addr in 0x1b..0x20 {
+                let reg = ControlRegisterU8::raw(saved_bank, addr).ok_or(EINVAL)?;
+                *data.next().ok_or(EINVAL)? = driver.read(reg)?;
+            }
+            for reg in REGS_DUMP_PHY {
+                for byte in driver.read_phy(reg)?.to_le_bytes() {
+                    *data.next().ok_or(EINVAL)? = byte;
+                }
+            }
+
+            // Leave no trace of the dump, although `switch_bank` copes with any bank
+            driver.select_bank(saved_bank)
+        }();
+    }
+
+    fn get_sset_count(
+        _dev: &net::Device,
+        _adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        sset: i32,
+    ) -> Result<i32> {
+        match sset as u32 {
+            bindings::ethtool_stringset_ETH_SS_STATS => Ok(ETHTOOL_STATS.len() as _),
+            bindings::ethtool_stringset_ETH_SS_PRIV_FLAGS => Ok(ETHTOOL_PRIV_FLAGS.len() as _),
+            bindings::ethtool_stringset_ETH_SS_TEST => Ok(ETHTOOL_TESTS.len() as _),
+            _ => Err(EOPNOTSUPP),
+        }
+    }
+
+    fn get_strings(
+        _dev: &net::Device,
+        _adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        stringset: u32,
+        data: &mut [u8],
+    ) {
+        let names: &mut dyn Iterator<Item = &str> = match stringset {
+            bindings::ethtool_stringset_ETH_SS_STATS => &mut ETHTOOL_STATS.iter().map(|(n, _)| *n),
+            bindings::ethtool_stringset_ETH_SS_PRIV_FLAGS => {
+                &mut ETHTOOL_PRIV_FLAGS.iter().copied()
+            }
+            bindings::ethtool_stringset_ETH_SS_TEST => &mut ETHTOOL_TESTS.iter().copied(),
+            _ => return,
+        };
+
+        let len = bindings::ETH_GSTRING_LEN as usize;
+        for (name, string) in names.zip(data.chunks_mut(len)) {
+            string[..name.len()].copy_from_slice(name.as_bytes());
+        }
+    }
+
+    // The loopback test resets the chip, so it refuses to run while the
+    // interface is up instead of disturbing live traffic. 0 means passed.
+    fn self_test(
+        dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        test: &mut bindings::ethtool_test,
+        data: &mut [u64],
+    ) {
+        // SAFETY: `dev` is the registered netdev the callback is called for.
+        let running = unsafe { bindings::netif_running(raw_netdev(dev)) };
+        let mut driver = adapter.driver.lock();
+        let registers = driver.check_revision();
+        let loopback = if test.flags & bindings::ethtool_test_flags_ETH_TEST_FL_OFFLINE == 0 {
+            Ok(())
+        } else if running {
+            dev_warn!(
+                from_dev(&driver.spidev),
+                "loopback test needs the interface down\n"
+            );
+            Err(EBUSY)
+        } else {
+            driver.test_loopback()
+        };
+
+        for (result, value) in [registers, loopback].into_iter().zip(data.iter_mut()) {
+            *value = result.is_err() as u64;
+            if result.is_err() {
+                test.flags |= bindings::ethtool_test_flags_ETH_TEST_FL_FAILED;
+            }
+        }
+    }
+
+    fn get_ethtool_stats(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _stats: &mut bindings::ethtool_stats,
+        data: &mut [u64],
+    ) {
+        let driver = adapter.driver.lock();
+        for ((_, get), value) in ETHTOOL_STATS.iter().zip(data.iter_mut()) {
+            *value = get(&driver);
+        }
+    }
+
+    // ethtool tunables only take the ids the core defines, none of which is
+    // about flow control, so the burst reserve is a private flag. Its size
+    // comes from `microchip,rx-burst-reserve`, or `RX_RESERVE_DEFAULT`.
+    fn get_priv_flags(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+    ) -> u32 {
+        if adapter.driver.lock().rx_reserve != 0 {
+            PRIV_FLAG_RX_RESERVE
+        } else {
+            0
+        }
+    }
+
+    fn set_priv_flags(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        flags: u32,
+    ) -> Result {
+        let mut driver = adapter.driver.lock();
+        let reserve = match (flags & PRIV_FLAG_RX_RESERVE != 0, driver.config.rx_reserve) {
+            (false, _) => 0,
+            (true, 0) => RX_RESERVE_DEFAULT,
+            (true, reserve) => reserve,
+        };
+        // The RX FIFO may have been shrunk through `set_ringparam` since probe
+        if reserve > driver.rx_fifo.end() - driver.rx_fifo.start() {
+            return Err(EINVAL);
+        }
+
+        driver.rx_reserve = reserve;
+        driver.update_flow_control()
+    }
+
+    // A 10BASE-T PHY has no Energy Efficient Ethernet. Reporting nothing
+    // supported rather than leaving the op out makes `ethtool --show-eee`
+    // succeed.
+    fn get_eee(
+        _dev: &net::Device,
+        _adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        eee: &mut bindings::ethtool_eee,
+    ) -> Result {
+        eee.supported = 0;
+        eee.advertised = 0;
+        eee.lp_advertised = 0;
+        eee.eee_active = 0;
+        eee.eee_enabled = 0;
+        eee.tx_lpi_enabled = 0;
+        eee.tx_lpi_timer = 0;
+        Ok(())
+    }
+
+    // Software timestamps only, there is no PTP clock
+    fn get_ts_info(
+        _dev: &net::Device,
+        _adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        info: &mut bindings::ethtool_ts_info,
+    ) -> Result {
+        info.so_timestamping = bindings::SOF_TIMESTAMPING_TX_SOFTWARE
+            | bindings::SOF_TIMESTAMPING_RX_SOFTWARE
+            | bindings::SOF_TIMESTAMPING_SOFTWARE;
+        info.phc_index = -1;
+        Ok(())
+    }
+
+    // Wake-up relies on the magic packet filter raising the IRQ, so it's
+    // unavailable when polling
+    fn get_wol(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        wol: &mut bindings::ethtool_wolinfo,
+    ) {
+        let driver = adapter.driver.lock();
+        if driver.irq.is_some() {
+            wol.supported = bindings::WAKE_MAGIC;
+            wol.wolopts = driver.wol;
+        }
+    }
+
+    fn set_wol(
+        _dev: &net::Device,
+        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        wol: &bindings::ethtool_wolinfo,
+    ) -> Result {
+        let mut driver = adapter.driver.lock();
+        if driver.irq.is_none() {
+            return Err(EOPNOTSUPP);
+        }
+        if wol.wolopts & !bindings::WAKE_MAGIC != 0 {
             return Err(EINVAL);
         }
 
-        self.next_packet_ptr = *range.start();
-
-        self.write(ERXST, Command::Wcr, *range.start())?;
-
-        let erxrdpt = Self::erxrdpt_workaround(self.next_packet_ptr, range);
-        self.write(ERXRDPT, Command::Wcr, erxrdpt)?;
+        driver.wol = wol.wolopts;
+        // SAFETY: `raw_device` is valid as long as `spidev` is alive.
+        unsafe { bindings::device_set_wakeup_enable(driver.spidev.raw_device(), wol.wolopts != 0) };
 
-        self.write(ERXND, Command::Wcr, *range.end())
+        Ok(())
     }
+}
 
-    fn init_txfifo(&mut self, range: &FifoRange) -> Result {
-        if range.is_empty()
-            || !TXFIFO_INIT.contains(range.start())
-            || !TXFIFO_INIT.contains(range.end())
+impl irq::ThreadedHandler for Enc28j60Adapter {
+    type Data = Arc<Self>;
+
+    fn handle_threaded_irq(adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> irq::Return {
+        // Deasserts INT before the line is unmasked, `IrqWorkHandler` sets
+        // INTIE again once it's done, which asserts INT anew for anything
+        // still pending
         {
-            return Err(EINVAL);
+            let mut driver = adapter.driver.lock();
+            if driver.netdev_reg.is_some() {
+                let _ = driver.write(EIE, Command::Bfc, eie::INTIE);
+            }
         }
 
-        self.write(ETXST, Command::Wcr, *range.start())?;
-        self.write(ETXND, Command::Wcr, *range.end())
-    }
+        adapter
+            .workqueue
+            .enqueue_adapter::<IrqWorkHandler>(adapter.into());
 
-    fn set_random_macaddr(&mut self, netdev: &net::Device) -> Result {
-        netdev.eth_hw_addr_random();
-        self.set_hw_macaddr(netdev)
+        irq::Return::Handled
     }
+}
 
-    fn set_hw_macaddr(&mut self, netdev: &net::Device) -> Result {
-        let dev_addr = netdev.device_address();
+struct LinkFile;
 
-        dev_info!(
-            from_dev(&self.spidev),
-            "MAC address: {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}\n",
-            dev_addr[0],
-            dev_addr[1],
-            dev_addr[2],
-            dev_addr[3],
-            dev_addr[4],
-            dev_addr[5]
-        );
+impl enc28j60_debugfs::File for LinkFile {
+    const NAME: &'static CStr = c_str!("link");
 
-        for (reg, addr) in [MAADR1, MAADR2, MAADR3, MAADR4, MAADR5, MAADR6]
-            .iter()
-            .zip(dev_addr)
-        {
-            self.write(*reg, Command::Wcr, *addr)?;
+    fn show(adapter: &Enc28j60Adapter, m: &mut enc28j60_debugfs::SeqFile) -> Result {
+        let driver = adapter.driver.lock();
+
+        writeln!(m, "link: {}", if driver.link_up { "up" } else { "down" })?;
+        match driver.link_changed {
+            Some(changed) => {
+                // SAFETY: FFI call without preconditions.
+                let elapsed = unsafe { bindings::ktime_get() } - changed;
+                writeln!(m, "since_last_change_ms: {}", elapsed / 1_000_000)?;
+            }
+            None => writeln!(m, "since_last_change_ms: -")?,
         }
 
         Ok(())
     }
+}
 
-    fn handle_rx(&mut self) -> Result<bool> {
-        let packet_count = self.read(EPKTCNT)?;
-        if packet_count == 0 {
-            return Ok(false);
+// Everything worth attaching to a bug report in one read, as `key: value`
+// lines. Taken under the driver lock so that it is one consistent snapshot.
+struct StateFile;
+
+impl enc28j60_debugfs::File for StateFile {
+    const NAME: &'static CStr = c_str!("state");
+
+    fn show(adapter: &Enc28j60Adapter, m: &mut enc28j60_debugfs::SeqFile) -> Result {
+        let mut driver = adapter.driver.lock();
+
+        writeln!(m, "revision: {:#04x}", driver.read(EREVID)?)?;
+        writeln!(m, "mtu: {}", driver.mtu)?;
+        writeln!(m, "max_frame_len: {}", driver.max_frame_len)?;
+        writeln!(m, "link: {}", if driver.link_up { "up" } else { "down" })?;
+        writeln!(m, "rx_flow_paused: {}", driver.rx_paused as u8)?;
+        writeln!(m, "rx_frozen: {}", driver.rx_frozen as u8)?;
+        writeln!(m, "rx_pause: {}", driver.rx_pause as u8)?;
+        writeln!(m, "tx_fifo_ready: {}", driver.tx_fifo_ready as u8)?;
+        writeln!(m, "tx_pending: {}", adapter.tx_queue.lock().len())?;
+        writeln!(m, "tx_in_flight: {}", driver.tx_in_flight as u8)?;
+        writeln!(m, "rx_pool: {}", driver.rx_pool.len())?;
+        writeln!(m, "next_packet_ptr: {:#06x}", driver.next_packet_ptr)?;
+
+        for (name, reg) in [
+            ("ERDPT", ERDPT),
+            ("EWRPT", EWRPT),
+            ("ETXST", ETXST),
+            ("ETXND", ETXND),
+            ("ERXST", ERXST),
+            ("ERXND", ERXND),
+            ("ERXRDPT", ERXRDPT),
+            ("ERXWRPT", ERXWRPT),
+        ] {
+            writeln!(m, "{}: {:#06x}", name, driver.read_stable(reg)?)?;
+        }
+        for (name, reg) in [
+            ("EIE", EIE),
+            ("EIR", EIR),
+            ("ESTAT", ESTAT),
+            ("ECON1", ECON1),
+            ("ECON2", ECON2),
+            ("ERXFCON", ERXFCON),
+            ("EPKTCNT", EPKTCNT),
+            ("EFLOCON", EFLOCON),
+            ("MACON1", MACON1),
+            ("MACON3", MACON3),
+        ] {
+            writeln!(m, "{}: {:#04x}", name, driver.read(reg)?)?;
+        }
+        for (name, reg) in [("PHCON1", PHCON1), ("PHSTAT2", PHSTAT2)] {
+            writeln!(m, "{}: {:#06x}", name, driver.read_phy(reg)?)?;
         }
 
-        for _ in 0..packet_count {
-            self.handle_rx_packet()?;
-            self.write(ECON2, Command::Bfs, econ2::PKTDEC)?;
+        for (name, get) in ETHTOOL_STATS {
+            writeln!(m, "{}: {}", name, get(&driver))?;
         }
 
-        Ok(true)
+        Ok(())
     }
+}
 
-    fn handle_rx_packet(&mut self) -> Result {
-        let mut rsv = [0; RxStatusVector::size()];
-        self.read_buffer(self.next_packet_ptr, &mut rsv)?;
-        let rsv = RxStatusVector::new(&rsv);
+// Just the ring state, small enough to `watch` while the RX FIFO fills up
+struct FifoFile;
 
-        if !rsv.status(RsvStatus::RxOk) || rsv.byte_count > ETH_MAX_FRAME_LEN {
-            dev_err!(
-                from_dev(&self.spidev),
-                "RX failed: {:?} Crc={} LengthCheckError={}\n",
-                rsv,
-                rsv.status(RsvStatus::CrcError),
-                rsv.status(RsvStatus::LengthCheckError)
-            );
-        } else {
-            let netdev = self.netdev();
-            let skb = netdev.alloc_skb_ip_align(rsv.byte_count as _)?;
-            let room = skb.put(rsv.byte_count as _);
+impl enc28j60_debugfs::File for FifoFile {
+    const NAME: &'static CStr = c_str!("fifo");
 
-            let read_ptr = Self::next_rx_start_ptr(self.next_packet_ptr);
-            self.read_buffer(read_ptr, room)?;
+    fn show(adapter: &Enc28j60Adapter, m: &mut enc28j60_debugfs::SeqFile) -> Result {
+        let mut driver = adapter.driver.lock();
 
-            skb.set_protocol(skb.eth_type_trans(&netdev));
-            netdev.netif_rx(&skb);
+        // Before the reads below select banks of their own
+        writeln!(m, "bank: {}", driver.bank as u8)?;
+        for (name, reg) in [
+            ("ERXST", ERXST),
+            ("ERXND", ERXND),
+            ("ERXRDPT", ERXRDPT),
+            ("ERXWRPT", ERXWRPT),
+        ] {
+            writeln!(m, "{}: {:#06x}", name, driver.read_stable(reg)?)?;
+        }
+        writeln!(m, "next_packet_ptr: {:#06x}", driver.next_packet_ptr)?;
+        writeln!(m, "EPKTCNT: {}", driver.read(EPKTCNT)?)?;
+        writeln!(m, "rx_free: {}", driver.rx_fifo_free()?)?;
+        writeln!(m, "rx_used: {}", driver.rx_fifo_used()?)?;
+        for (name, reg) in [("ETXST", ETXST), ("ETXND", ETXND)] {
+            writeln!(m, "{}: {:#06x}", name, driver.read_stable(reg)?)?;
         }
-
-        self.next_packet_ptr = rsv.next_ptr;
-        let erxrdpt = Self::erxrdpt_workaround(rsv.next_ptr, &RXFIFO_INIT);
-        self.write(ERXRDPT, Command::Wcr, erxrdpt)?;
 
         Ok(())
     }
-
-    fn next_rx_start_ptr(ptr: u16) -> u16 {
-        let rsv_end = ptr + RxStatusVector::size() as u16;
-        if RXFIFO_INIT.contains(&rsv_end) {
-            rsv_end
-        } else {
-            rsv_end - (RXFIFO_INIT.end() - RXFIFO_INIT.start() + 1)
-        }
-    }
-}
-
-struct Enc28j60Adapter {
-    driver: Mutex<Enc28j60Driver>,
-    workqueue: workqueue::BoxedQueue,
-    irq_work: workqueue::Work,
-    tx_work: workqueue::Work,
-    tx_skb: SpinLock<Option<ARef<net::SkBuff>>>,
 }
 
-// SAFETY:
-//  - `Send` for `workqueue::Work` and `ARef<SkBuff>`.
-//     The types lack Send due to holding raw pointers.
-//     It's safe to send them to other threads.
-//     Dereferencing raw pointers is unsafe anyway.
-//  - `Sync` for `workqueue::Work`.
-//     The type lacks Sync due to holding raw pointers.
-//     `Work` wraps `work_struct` which is a thread-safe type.
-unsafe impl Send for Enc28j60Adapter {}
-unsafe impl Sync for Enc28j60Adapter {}
-
-impl Enc28j60Adapter {
-    fn try_new(spidev: spi::Device) -> Result<Arc<Self>> {
-        let mut driver = Enc28j60Driver {
-            bank: Bank::Bank0,
-            spidev,
-            netdev_reg: None,
-            irq: None,
-            next_packet_ptr: 0,
-            xfer_buf: [0; ETH_MAX_FRAME_LEN as usize + 4],
-        };
-
-        driver.init_hardware()?;
+// Largest frame read in a single SPI message with its RSV, in bytes
+struct RxSingleReadFile;
 
-        let mut adapter = UniqueArc::try_new(Enc28j60Adapter {
-            driver: Mutex::new(driver),
-            workqueue: workqueue::Queue::try_new(fmt!("enc28j60_wq"))?,
-            // SAFETY: Initialized immediately in the following statements.
-            irq_work: unsafe { workqueue::Work::new() },
-            tx_work: unsafe { workqueue::Work::new() },
-            tx_skb: unsafe { SpinLock::new(None) },
-        })?;
-        kernel::init_work_item_adapter!(IrqWorkHandler, &adapter);
-        kernel::init_work_item_adapter!(TxWorkHandler, &adapter);
-        kernel::spinlock_init!(
-            unsafe { Pin::new_unchecked(&mut adapter.tx_skb) },
-            "enc_skb"
-        );
+impl enc28j60_debugfs::File for RxSingleReadFile {
+    const NAME: &'static CStr = c_str!("rx_single_read_max");
+    const WRITABLE: bool = true;
 
-        Ok(adapter.into())
+    fn show(adapter: &Enc28j60Adapter, m: &mut enc28j60_debugfs::SeqFile) -> Result {
+        writeln!(m, "{}", adapter.driver.lock().rx_single_read_max)?;
+        Ok(())
     }
 
-    fn request_irq(self: &Arc<Self>) -> Result {
-        let mut driver = self.driver.lock();
+    fn write(adapter: &Enc28j60Adapter, input: &str) -> Result {
+        let max: u16 = input.parse().map_err(|_| EINVAL)?;
 
-        let irq = driver.spidev.get_irq() as _;
-        driver.irq = Some(irq::ThreadedRegistration::try_new(
-            irq,
-            self.clone(),
-            irq::flags::SHARED,
-            fmt!("enc28j60_{irq}"),
-        )?);
+        let mut driver = adapter.driver.lock();
+        let head_len = RxStatusVector::size() + max as usize;
+        if head_len > driver.xfer_buf.len() || head_len > driver.rx_chunk_max {
+            return Err(EINVAL);
+        }
 
+        driver.rx_single_read_max = max;
         Ok(())
     }
+}
 
-    fn register_netdev(self: &Arc<Self>) -> Result {
-        let mut driver = self.driver.lock();
-        let mut netdev_reg = net::Registration::try_new(&driver.spidev)?;
+// Writing 1 stops reception and 0 resumes it, to inspect the RX FIFO while it
+// doesn't change. Carrier and TX are unaffected. Only available in debug builds.
+struct RxFreezeFile;
 
-        let netdev = netdev_reg.dev_get();
-        driver.set_random_macaddr(&netdev)?;
-        netdev.set_if_port(bindings::IF_PORT_10BASET as _);
-        netdev.set_irq(driver.spidev.get_irq());
+impl enc28j60_debugfs::File for RxFreezeFile {
+    const NAME: &'static CStr = c_str!("rx_freeze");
+    const WRITABLE: bool = true;
 
-        netdev_reg.register(self.clone())?;
-        driver.netdev_reg = Some(netdev_reg);
+    fn show(adapter: &Enc28j60Adapter, m: &mut enc28j60_debugfs::SeqFile) -> Result {
+        writeln!(m, "{}", adapter.driver.lock().rx_frozen as u8)?;
         Ok(())
     }
-}
 
-impl driver::DeviceRemoval for Enc28j60Adapter {
-    fn device_remove(&self) {
-        drop({
-            let mut driver = self.driver.lock();
-            driver.irq.take()
-        });
+    fn write(adapter: &Enc28j60Adapter, input: &str) -> Result {
+        let frozen = match input {
+            "0" => false,
+            "1" => true,
+            _ => return Err(EINVAL),
+        };
 
-        self.workqueue.flush();
+        let mut driver = adapter.driver.lock();
+        // RX is off while down anyway and `open` starts unfrozen
+        let netdev = driver.netdev();
+        // SAFETY: The netdev is registered while debugfs files exist.
+        if !unsafe { bindings::netif_running(raw_netdev(&netdev)) } {
+            return Err(ENETDOWN);
+        }
+        if frozen == driver.rx_frozen {
+            return Ok(());
+        }
 
-        drop({
-            let mut driver = self.driver.lock();
-            driver.netdev_reg.take()
-        });
+        // ERDPT, ERXRDPT and `next_packet_ptr` aren't touched while frozen,
+        // so reception resumes where it stopped. Frames still pending then
+        // raise the IRQ as soon as PKTIE is set again.
+        if frozen {
+            driver.write(ECON1, Command::Bfc, econ1::RXEN)?;
+            driver.write(EIE, Command::Bfc, eie::PKTIE)?;
+        } else {
+            driver.write(EIE, Command::Bfs, eie::PKTIE)?;
+            driver.write(ECON1, Command::Bfs, econ1::RXEN)?;
+        }
+        driver.rx_frozen = frozen;
+
+        Ok(())
     }
 }
 
-#[vtable]
-impl net::DeviceOperations for Enc28j60Adapter {
-    type Data = Arc<Enc28j60Adapter>;
+// Raw register access for bring-up, only available in debug builds.
+// Writing "<bank> <addr>" selects a register which is then read by reading
+// the file, and "<bank> <addr> <value>" also writes it. Numbers are in hex.
+struct RegFile;
 
-    fn open(dev: &net::Device, adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Result {
-        let mut driver = adapter.driver.lock();
+impl enc28j60_debugfs::File for RegFile {
+    const NAME: &'static CStr = c_str!("reg");
+    const WRITABLE: bool = true;
 
-        driver.disable_hardware()?;
-        driver.init_hardware()?;
-        driver.set_hw_macaddr(dev)?;
-        driver.enable_hardware()?;
-        driver.check_link_status()?;
+    fn show(adapter: &Enc28j60Adapter, m: &mut enc28j60_debugfs::SeqFile) -> Result {
+        let mut driver = adapter.driver.lock();
 
-        dev.netif_start_queue();
+        if let Some(reg) = driver.debug_reg {
+            let value = driver.read(reg)?;
+            writeln!(m, "{:?} = {:#04x}", reg, value)?;
+        }
 
         Ok(())
     }
 
-    // Don't use `netdev_reg` as it might be None
-    fn stop(dev: &net::Device, adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Result {
+    fn write(adapter: &Enc28j60Adapter, input: &str) -> Result {
+        let parse = |s: Option<&str>| {
+            let s = s.ok_or(EINVAL)?;
+            u8::from_str_radix(s.trim_start_matches("0x"), 16).map_err(|_| EINVAL)
+        };
+
+        let mut args = input.split_whitespace();
+        let bank = Bank::from_index(parse(args.next())?).ok_or(EINVAL)?;
+        let reg = ControlRegisterU8::raw(bank, parse(args.next())?).ok_or(EINVAL)?;
+        let value = args.next().map(|v| parse(Some(v))).transpose()?;
+
         let mut driver = adapter.driver.lock();
+        driver.debug_reg = Some(reg);
 
-        dev.netif_stop_queue();
+        if let Some(value) = value {
+            driver.write(reg, Command::Wcr, value)?;
 
-        driver.disable_hardware()?;
+            // A raw ECON1 write may have changed BSEL behind `switch_bank`
+            if reg == ECON1 {
+                driver.resync_bank()?;
+            }
+        }
 
         Ok(())
     }
+}
 
-    fn start_xmit(
-        skb: &net::SkBuff,
-        dev: &net::Device,
-        adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>,
-    ) -> net::NetdevTx {
-        dev.netif_stop_queue();
+// Runs in hard IRQ context, so only hands the servicing to the poll worker.
+// SPI transfers sleep, so the chip can't be serviced from here.
+unsafe extern "C" fn poll_timer_fn(timer: *mut bindings::hrtimer) -> bindings::hrtimer_restart {
+    // SAFETY: The timer is embedded in an adapter which is alive while the
+    // timer runs, as `poll_ref` holds a reference to it.
+    let adapter = unsafe { &*kernel::container_of!(timer, Enc28j60Adapter, poll_timer) };
 
-        *adapter.tx_skb.lock_irqdisable() = Some(skb.into());
+    let poll_ref = adapter.poll_ref.load(Ordering::Acquire);
+    if poll_ref.is_null() {
+        return bindings::hrtimer_restart_HRTIMER_NORESTART;
+    }
 
+    // A run still queued from the previous poll covers this one as well
+    if adapter.poll_work_ref.load(Ordering::Acquire).is_null() {
+        // SAFETY: `poll_ref` came from `into_foreign` and is only released
+        // after the timer is cancelled.
+        let borrowed = unsafe { Arc::<Enc28j60Adapter>::borrow(poll_ref) };
+        let work_ref = Arc::<Enc28j60Adapter>::from(borrowed).into_foreign();
         adapter
-            .workqueue
-            .enqueue_adapter::<TxWorkHandler>(adapter.into());
+            .poll_work_ref
+            .store(work_ref as _, Ordering::Release);
+        // SAFETY: The worker is created along with the timer when polling and
+        // only destroyed after it's cancelled. `poll_work` was initialized in
+        // `try_new`.
+        unsafe {
+            bindings::kthread_queue_work(
+                adapter.poll_worker.load(Ordering::Acquire),
+                adapter.poll_work.get(),
+            )
+        };
+    }
 
-        net::NetdevTx::Ok
+    // SAFETY: `timer` is the running timer, which may be forwarded.
+    unsafe { bindings::hrtimer_forward(timer, bindings::ktime_get(), adapter.poll_interval) };
+    bindings::hrtimer_restart_HRTIMER_RESTART
+}
+
+// Runs on the poll worker, see `create_poll_worker`
+unsafe extern "C" fn poll_work_fn(work: *mut bindings::kthread_work) {
+    // SAFETY: The work is embedded in an adapter which is alive while the
+    // work is queued, as `poll_work_ref` holds a reference to it.
+    let adapter = unsafe { &*kernel::container_of!(work, Enc28j60Adapter, poll_work) };
+
+    let work_ref = adapter
+        .poll_work_ref
+        .swap(ptr::null_mut(), Ordering::AcqRel);
+    if !work_ref.is_null() {
+        // SAFETY: `work_ref` came from `into_foreign` in `poll_timer_fn`.
+        let adapter = unsafe { Arc::<Enc28j60Adapter>::from_foreign(work_ref) };
+        service(&adapter);
     }
 }
 
-impl irq::ThreadedHandler for Enc28j60Adapter {
-    type Data = Arc<Self>;
+// Hard IRQ context as well, the IRQ work turns PKTIE back on
+unsafe extern "C" fn coalesce_timer_fn(timer: *mut bindings::hrtimer) -> bindings::hrtimer_restart {
+    // SAFETY: The timer is embedded in an adapter which is alive while the
+    // timer runs, as `coalesce_ref` holds a reference to it.
+    let adapter = unsafe { &*kernel::container_of!(timer, Enc28j60Adapter, coalesce_timer) };
 
-    fn handle_threaded_irq(adapter: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> irq::Return {
+    let coalesce_ref = adapter.coalesce_ref.load(Ordering::Acquire);
+    if !coalesce_ref.is_null() {
+        adapter.coalesce_expired.store(true, Ordering::Release);
+        // SAFETY: `coalesce_ref` came from `into_foreign` and is only released
+        // after the timer is cancelled.
+        let borrowed = unsafe { Arc::<Enc28j60Adapter>::borrow(coalesce_ref) };
         adapter
             .workqueue
-            .enqueue_adapter::<IrqWorkHandler>(adapter.into());
-
-        irq::Return::Handled
+            .enqueue_adapter::<IrqWorkHandler>(borrowed.into());
     }
+
+    bindings::hrtimer_restart_HRTIMER_NORESTART
 }
 
-struct IrqWorkHandler;
+unsafe extern "C" fn silicon_rev_show(
+    _dev: *mut bindings::device,
+    attr: *mut bindings::device_attribute,
+    buf: *mut core::ffi::c_char,
+) -> isize {
+    // SAFETY: `attr` is the `attr` of the adapter's `rev_attr`, whose `var`
+    // points at `revision`. The adapter outlives the sysfs file.
+    let revision = unsafe {
+        let ext = kernel::container_of!(attr, bindings::dev_ext_attribute, attr);
+        *((*ext).var as *const u8)
+    };
 
-kernel::impl_work_adapter!(IrqWorkHandler, Enc28j60Adapter, irq_work, |adapter| {
+    // SAFETY: `buf` is the PAGE_SIZE buffer sysfs gives to `show`.
+    unsafe {
+        bindings::sysfs_emit(
+            buf,
+            c_str!("%s (%#04x)\n").as_char_ptr(),
+            Revision::name(revision).as_char_ptr(),
+            revision as core::ffi::c_uint,
+        ) as _
+    }
+}
+
+// Services the chip after an interrupt, from `IrqWorkHandler`, or a poll,
+// from `poll_work_fn`
+fn service(adapter: &Arc<Enc28j60Adapter>) {
     let _ = move || -> Result {
         let mut driver = adapter.driver.lock();
+        // A requeued run may come after `device_remove`
+        if driver.netdev_reg.is_none() {
+            return Ok(());
+        }
+        // The wake IRQ is left to the PM core, `device_resume` resets the
+        // chip and picks up from there
+        if driver.suspended {
+            return Ok(());
+        }
 
         driver.write(EIE, Command::Bfc, eie::INTIE)?;
 
+        if driver.rx_deferred {
+            let expired = adapter.coalesce_expired.swap(false, Ordering::AcqRel);
+            let frames = driver.rx_coalesce_frames;
+            if expired || (frames > 1 && driver.read(EPKTCNT)? as u32 >= frames) {
+                driver.rx_deferred = false;
+                if !driver.rx_frozen {
+                    driver.write(EIE, Command::Bfs, eie::PKTIE)?;
+                }
+            }
+        }
+
+        let mut rx_budget = RX_BUDGET;
         let mut iteration = false;
         while {
+            driver.update_flow_control()?;
+
             let eir = driver.read(EIR)?;
 
             if eir & eir::DMAIF != 0 {
@@ -501,41 +3880,126 @@ kernel::impl_work_adapter!(IrqWorkHandler, Enc28j60Adapter, irq_work, |adapter|
 
             if eir & eir::LINKIF != 0 {
                 iteration = true;
-                driver.check_link_status()?;
+                driver.handle_link_irq()?;
                 let _ = driver.read_phy(PHIR)?;
             }
 
-            if eir & eir::TXIF != 0 && eir & eir::TXERIF == 0 {
-                iteration = true;
-                let _ = adapter.tx_skb.lock().take();
-
-                driver.write(ECON1, Command::Bfc, econ1::TXRTS)?;
-                driver.netdev().netif_wake_queue();
-                driver.write(EIR, Command::Bfc, eir::TXIF)?;
-            }
-
+            // TXIF is also set when a transmission completes with an error, so
+            // TXERIF decides the outcome and the frame is completed only once.
             if eir & eir::TXERIF != 0 {
                 iteration = true;
-                let _ = adapter.tx_skb.lock().as_mut().take();
 
                 let tsv = driver.read_tsv()?;
-                dev_err!(from_dev(&driver.spidev), "TX failed: {:?}\n", tsv);
+                driver.update_stats(|stats| {
+                    stats.collisions += tsv.collision_count() as _;
+                    if tsv.status(TsvStatus::ExcessiveCollision) {
+                        stats.collisions += 16;
+                    }
+                });
+
+                // ESTAT tells an abort from a late collision, which points
+                // to a duplex mismatch or cabling fault. Both bits stay set
+                // until cleared, and the TX logic needs a reset to recover.
+                let estat = driver.read(ESTAT)?;
+                let late_collision = estat & estat::LATECOL != 0 || tsv.late_collision();
+                let aborted = estat & estat::TXABRT != 0 || tsv.aborted();
 
-                driver.write(ECON1, Command::Bfs, econ1::TXRTS)?;
                 driver.write(ECON1, Command::Bfc, econ1::TXRTS)?;
-                driver.init_txfifo(&TXFIFO_INIT)?;
+                driver.pulse_bit(ECON1, econ1::TXRST)?;
+                driver.write(ESTAT, Command::Bfc, estat::TXABRT | estat::LATECOL)?;
+                driver.init_txfifo(&driver.tx_fifo.clone())?;
+                driver.write(EIR, Command::Bfc, eir::TXERIF | eir::TXIF)?;
+
+                // Late collisions on a half-duplex link are usually transient,
+                // so the frame gets a few more attempts before it's dropped
+                let skb = adapter.tx_queue.lock().front();
+                let retried = match &skb {
+                    Some(skb) if late_collision && driver.tx_retries < TX_MAX_RETRIES => {
+                        driver.tx_retries += 1;
+                        // A retry that can't be started is dropped like one
+                        // out of attempts, the IRQ work must go on
+                        driver
+                            .start_tx(skb.head_data(), skb_csum_partial(skb))
+                            .is_ok()
+                    }
+                    _ => false,
+                };
+                if !retried {
+                    netif_msg!(
+                        driver,
+                        NETIF_MSG_TX_ERR_BIT,
+                        dev_err,
+                        "TX failed{}{}: {:?}\n",
+                        if aborted { ", aborted" } else { "" },
+                        if late_collision {
+                            ", late collision"
+                        } else {
+                            ""
+                        },
+                        tsv
+                    );
+                    driver.update_stats(|stats| {
+                        stats.tx_errors += 1;
+                        if aborted {
+                            stats.tx_aborted_errors += 1;
+                        }
+                        if late_collision {
+                            stats.tx_window_errors += 1;
+                        }
+                    });
+                    if let Some(skb) = skb {
+                        Enc28j60Driver::report_tx_error(&skb, &tsv);
+                    }
+
+                    let _ = adapter.tx_queue.lock().pop();
+                    driver.netdev().netif_wake_queue();
+                    adapter.start_queued_tx(&mut driver);
+                }
+            } else if eir & eir::TXIF != 0 {
+                iteration = true;
+                if let Some(skb) = adapter.tx_queue.lock().pop() {
+                    adapter.stats.tx(skb.head_data().len());
+                }
 
+                driver.write(ECON1, Command::Bfc, econ1::TXRTS)?;
+                driver.tx_in_flight = false;
                 driver.netdev().netif_wake_queue();
-                driver.write(EIR, Command::Bfc, eir::TXERIF | eir::TXIF)?;
+                driver.write(EIR, Command::Bfc, eir::TXIF)?;
+                adapter.start_queued_tx(&mut driver);
             }
 
             if eir & eir::RXERIF != 0 {
                 iteration = true;
+                driver.update_stats(|stats| {
+                    stats.rx_errors += 1;
+                    stats.rx_over_errors += 1;
+                });
+                // Frozen reception must leave the FIFO as it is
+                if !driver.rx_frozen {
+                    driver.reset_rx_ring()?;
+                }
                 driver.write(EIR, Command::Bfc, eir::RXERIF)?;
             }
 
-            if driver.handle_rx()? {
+            // Errata: PKTIF doesn't reliably report pending packets and may stay
+            // set while EPKTCNT is zero. It can't be cleared directly either (it
+            // drops when PKTDEC brings EPKTCNT to zero), so only EPKTCNT decides
+            // whether there is RX work and a stuck PKTIF never keeps us looping.
+            if driver.rx_frozen || driver.rx_deferred {
+                // PKTIE is off, pending frames stay in the FIFO until resumed
+                // or the coalescing delay is over
+            } else if rx_budget == 0 {
+                // Left to the next run, see below
+            } else if let received @ 1.. = driver.handle_rx(&adapter.stats, rx_budget)? {
+                rx_budget -= received;
                 iteration = true;
+            } else if eir & eir::PKTIF != 0 {
+                // A packet may have arrived after `handle_rx` read the count
+                if driver.read(EPKTCNT)? != 0 {
+                    iteration = true;
+                } else {
+                    dev_dbg!(from_dev(&driver.spidev), "PKTIF set with EPKTCNT == 0\n");
+                }
             }
 
             iteration
@@ -543,34 +4007,119 @@ kernel::impl_work_adapter!(IrqWorkHandler, Enc28j60Adapter, irq_work, |adapter|
             iteration = false;
         }
 
-        driver.write(EIE, Command::Bfs, eie::INTIE)
+        driver.update_flow_control()?;
+
+        // `stop` cancels the timers under the driver lock once the interface
+        // is no longer running, so the timer must not be armed after that
+        let usecs = driver.rx_coalesce_usecs;
+        // SAFETY: `netdev_reg` is Some, checked above.
+        let running = unsafe { bindings::netif_running(raw_netdev(&driver.netdev())) };
+        if running
+            && rx_budget < RX_BUDGET
+            && usecs != 0
+            && !driver.rx_deferred
+            && !driver.rx_frozen
+        {
+            driver.write(EIE, Command::Bfc, eie::PKTIE)?;
+            driver.rx_deferred = true;
+            adapter.start_coalesce_timer(usecs);
+        }
+
+        driver.write(EIE, Command::Bfs, eie::INTIE)?;
+
+        driver.refill_rx_pool();
+
+        // `netdev_notify_peers` takes the RTNL lock, which `open`/`stop` hold
+        // while taking the driver lock.
+        let netdev = core::mem::take(&mut driver.notify_peers).then(|| driver.netdev());
+        drop(driver);
+        if let Some(netdev) = netdev {
+            // SAFETY: `netdev` is a registered net device and no locks are held.
+            unsafe { bindings::netdev_notify_peers(raw_netdev(&netdev)) };
+        }
+
+        // With the driver lock released in between, TX and the other work
+        // get their turn under sustained RX. PKTIF can't be relied on to
+        // raise the IRQ again (see above), so the rest is queued directly.
+        if rx_budget == 0 {
+            adapter
+                .workqueue
+                .enqueue_adapter::<IrqWorkHandler>(adapter.clone());
+        }
+
+        Ok(())
     }();
+}
+
+struct IrqWorkHandler;
+
+kernel::impl_work_adapter!(IrqWorkHandler, Enc28j60Adapter, irq_work, |adapter| {
+    service(&adapter);
 });
 
 struct TxWorkHandler;
 
 kernel::impl_work_adapter!(TxWorkHandler, Enc28j60Adapter, tx_work, |adapter| {
     let _ = move || -> Result {
-        // DeviceOperations::start_xmit stores the RX `SkBuff`
-        let skb = adapter.tx_skb.lock().as_ref().unwrap().clone();
-        let skb_data = skb.head_data();
-
         let mut driver = adapter.driver.lock();
+        if driver.netdev_reg.is_none() {
+            return Ok(());
+        }
 
-        driver.write(EWRPT, Command::Wcr, *TXFIFO_INIT.start())?;
-        driver.write(
-            ETXND,
-            Command::Wcr,
-            TXFIFO_INIT.start() + skb_data.len() as u16,
-        )?;
-
-        driver.write_buffer(&[0])?;
-        driver.write_buffer(skb_data)?;
-
-        driver.write(ECON1, Command::Bfs, econ1::TXRTS)
+        adapter.start_queued_tx(&mut driver);
+        Ok(())
     }();
 });
 
+struct RxModeWorkHandler;
+
+kernel::impl_work_adapter!(
+    RxModeWorkHandler,
+    Enc28j60Adapter,
+    rx_mode_work,
+    |adapter| {
+        let _ = move || -> Result {
+            let mut driver = adapter.driver.lock();
+            let filter = *adapter.rx_mode.lock_irqdisable();
+            driver.set_rx_filter(filter)
+        }();
+    }
+);
+
+struct TxTimeoutWorkHandler;
+
+// Same recovery as for TXERIF, for a completion that never came
+kernel::impl_work_adapter!(
+    TxTimeoutWorkHandler,
+    Enc28j60Adapter,
+    tx_timeout_work,
+    |adapter| {
+        let _ = move || -> Result {
+            let mut driver = adapter.driver.lock();
+            if driver.netdev_reg.is_none() {
+                return Ok(());
+            }
+
+            dev_err!(from_dev(&driver.spidev), "TX timeout, resetting TX logic\n");
+            // The queue may have stalled before the head was handed to the
+            // chip, which is then still to be sent rather than an error
+            if core::mem::take(&mut driver.tx_in_flight) {
+                driver.update_stats(|stats| stats.tx_errors += 1);
+                let _ = adapter.tx_queue.lock().pop();
+            }
+
+            driver.write(ECON1, Command::Bfc, econ1::TXRTS)?;
+            driver.pulse_bit(ECON1, econ1::TXRST)?;
+            driver.init_txfifo(&driver.tx_fifo.clone())?;
+            driver.write(EIR, Command::Bfc, eir::TXERIF | eir::TXIF)?;
+
+            driver.netdev().netif_wake_queue();
+            adapter.start_queued_tx(&mut driver);
+            Ok(())
+        }();
+    }
+);
+
 type IdInfo = ();
 
 impl spi::Driver for Enc28j60Adapter {
@@ -591,9 +4140,13 @@ impl spi::Driver for Enc28j60Adapter {
         _of_id_info: Option<&Self::IdInfo>,
         _spi_id_info: Option<&Self::IdInfo>,
     ) -> Result<Self::Data> {
-        let adapter = Enc28j60Adapter::try_new(spidev)?;
-        adapter.request_irq()?;
+        let config = BoardConfig::from_dt(&spidev)?;
+        let adapter = Enc28j60Adapter::try_new(spidev, config)?;
+        if adapter.poll_interval == 0 {
+            adapter.request_irq()?;
+        }
         adapter.register_netdev()?;
+        adapter.register_debugfs();
 
         Ok(adapter)
     }
@@ -605,6 +4158,14 @@ impl spi::Driver for Enc28j60Adapter {
     fn shutdown(spidev: spi::Device, _data: <Self::Data as ForeignOwnable>::Borrowed<'_>) {
         dev_info!(from_dev(&spidev), "enc28j60rs SPI shutdown\n");
     }
+
+    fn suspend(_spidev: spi::Device, data: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Result {
+        Arc::from(data).device_suspend()
+    }
+
+    fn resume(_spidev: spi::Device, data: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Result {
+        Arc::from(data).device_resume()
+    }
 }
 
 module_spi_driver! {
@@ -613,4 +4174,31 @@ module_spi_driver! {
     author: "Ryo Munakata <ryomnktml@gmail.com>",
     description: "ENC28J60 ethernet driver in Rust",
     license: "GPL",
+    params: {
+        notify_peers: bool {
+            default: false,
+            permissions: 0o644,
+            description: "Send gratuitous ARP/unsolicited NA when the link comes up",
+        },
+        reset_stats_on_open: bool {
+            default: false,
+            permissions: 0o644,
+            description: "Zero the interface statistics every time it is brought up",
+        },
+        tx_queue_len: u32 {
+            default: 2,
+            permissions: 0o444,
+            description: "Frames queued for TX, the one being sent included, before the stack is told to hold off (1-8)",
+        },
+        rx_filter: str {
+            default: b"unicast+broadcast",
+            permissions: 0o444,
+            description: "Default receive filter: unicast+broadcast, unicast or promiscuous",
+        },
+        poll_interval_us: u32 {
+            default: 0,
+            permissions: 0o444,
+            description: "Poll the chip with an hrtimer at this interval instead of using the IRQ (0 = IRQ)",
+        },
+    },
 }